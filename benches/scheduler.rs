@@ -0,0 +1,141 @@
+//! Dispatch throughput of `ThreadPool` against a bare pool of the original
+//! design, where every worker blocked on one shared
+//! `Mutex<mpsc::Receiver<Job>>`.
+//!
+//! Both dispatch through a mutex, so the gap is what the pool's per-job
+//! bookkeeping costs: priorities, statistics and idle tracking. Each round
+//! has a few producer threads submit many tiny jobs and waits until all of
+//! them ran. Run with
+//!
+//! ```text
+//! cargo bench --bench scheduler
+//! ```
+//!
+//! On a single CPU, the pool managed about half the bare throughput. Much
+//! of the gap is reading the clock for the queue-wait and execution-time
+//! statistics:
+//!
+//! ```text
+//!     pool producers=1 median=   38.17ms      1309816 jobs/s
+//!     bare producers=1 median=   19.37ms      2580794 jobs/s
+//!     pool producers=4 median=  111.37ms      1795826 jobs/s
+//!     bare producers=4 median=   52.95ms      3776939 jobs/s
+//! ```
+
+use rust_server::ThreadPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WORKERS: usize = 4;
+const JOBS_PER_PRODUCER: usize = 50_000;
+const ROUNDS: usize = 5;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// The pool as it was before it grew its features.
+struct MutexPool {
+    workers: Vec<thread::JoinHandle<()>>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+impl MutexPool {
+    fn new(count: usize) -> MutexPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..count)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    let message = receiver.lock().unwrap().recv();
+                    match message {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        MutexPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+    }
+}
+
+impl Drop for MutexPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// Submit `producers * JOBS_PER_PRODUCER` jobs through `submit` and return
+/// how long it took until the last one finished.
+fn round<P>(pool: Arc<P>, producers: usize, submit: fn(&P, Box<dyn FnOnce() + Send>)) -> Duration
+where
+    P: Send + Sync + 'static,
+{
+    let total = producers * JOBS_PER_PRODUCER;
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..producers)
+        .map(|_| {
+            let pool = Arc::clone(&pool);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                for _ in 0..JOBS_PER_PRODUCER {
+                    let done = Arc::clone(&done);
+                    submit(
+                        &pool,
+                        Box::new(move || {
+                            done.fetch_add(1, Ordering::Relaxed);
+                        }),
+                    );
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    while done.load(Ordering::Relaxed) < total {
+        thread::yield_now();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, producers: usize, times: &mut [Duration]) {
+    times.sort();
+    let median = times[times.len() / 2];
+    let jobs = (producers * JOBS_PER_PRODUCER) as f64;
+    eprintln!(
+        "{name:>8} producers={producers} median={median:>10.2?} {:>12.0} jobs/s",
+        jobs / median.as_secs_f64()
+    );
+}
+
+fn main() {
+    for producers in [1, 4] {
+        let pool = Arc::new(ThreadPool::new(WORKERS));
+        let mut times: Vec<_> = (0..ROUNDS)
+            .map(|_| round(Arc::clone(&pool), producers, |pool, job| pool.execute(job)))
+            .collect();
+        report("pool", producers, &mut times);
+
+        let pool = Arc::new(MutexPool::new(WORKERS));
+        let mut times: Vec<_> = (0..ROUNDS)
+            .map(|_| round(Arc::clone(&pool), producers, |pool, job| pool.execute(job)))
+            .collect();
+        report("bare", producers, &mut times);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+
+/// Which CPUs worker threads are pinned to; see
+/// [`ThreadPoolBuilder::cpu_affinity`](crate::ThreadPoolBuilder::cpu_affinity).
+///
+/// Worker `n` goes to the `n`th CPU of the list, wrapping around when there
+/// are more workers than CPUs. A worker that replaces a dead one keeps its
+/// CPU. Only supported on Linux.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuAffinity {
+    /// Spread workers over every CPU this process may run on.
+    RoundRobin,
+    /// Spread workers over these CPUs only, e.g. the cores of one NUMA node
+    /// so workers share its caches and memory.
+    Cpus(Vec<usize>),
+}
+
+impl CpuAffinity {
+    /// The CPUs to hand out, checked against the ones this process may use.
+    pub(crate) fn resolve(&self) -> io::Result<Vec<usize>> {
+        let available = imp::available_cpus()?;
+        match self {
+            CpuAffinity::RoundRobin => Ok(available),
+            CpuAffinity::Cpus(cpus) if cpus.is_empty() => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no CPUs to pin workers to",
+            )),
+            CpuAffinity::Cpus(cpus) => match cpus.iter().find(|cpu| !available.contains(cpu)) {
+                Some(cpu) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("CPU {cpu} is not available to this process"),
+                )),
+                None => Ok(cpus.clone()),
+            },
+        }
+    }
+}
+
+pub(crate) use imp::pin_current_thread;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{io, mem};
+
+    pub(crate) fn available_cpus() -> io::Result<Vec<usize>> {
+        // SAFETY: `cpu_set_t` is a plain bit set, valid when zeroed, and
+        // the kernel writes at most `size_of::<cpu_set_t>()` bytes into it.
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect())
+        }
+    }
+
+    /// Restrict the calling thread to `cpu`, which must come from
+    /// `available_cpus`.
+    pub(crate) fn pin_current_thread(cpu: usize) -> io::Result<()> {
+        // SAFETY: as above; `cpu` is below `CPU_SETSIZE` because it was one
+        // of the CPUs reported by `sched_getaffinity`.
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_SET(cpu, &mut set);
+            if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "CPU affinity is only supported on Linux",
+        )
+    }
+
+    pub(crate) fn available_cpus() -> io::Result<Vec<usize>> {
+        Err(unsupported())
+    }
+
+    pub(crate) fn pin_current_thread(_cpu: usize) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{BuildError, ThreadPool};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn workers_are_pinned_round_robin() {
+        let available = imp::available_cpus().unwrap();
+        let pool = ThreadPool::builder()
+            .num_threads(3)
+            .cpu_affinity(CpuAffinity::RoundRobin)
+            .build()
+            .unwrap();
+        let expected: Vec<_> = (0..3)
+            .map(|id| (id, Some(available[id % available.len()])))
+            .collect();
+
+        // Workers record their CPU once they have started.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.stats().worker_cpus != expected && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(pool.stats().worker_cpus, expected);
+    }
+
+    #[test]
+    fn unavailable_cpus_are_rejected() {
+        let err = ThreadPool::builder()
+            .cpu_affinity(CpuAffinity::Cpus(vec![usize::MAX]))
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, BuildError::Affinity(_)));
+
+        let err = ThreadPool::builder()
+            .cpu_affinity(CpuAffinity::Cpus(Vec::new()))
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, BuildError::Affinity(_)));
+    }
+}
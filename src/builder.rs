@@ -0,0 +1,360 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::affinity::CpuAffinity;
+use crate::context::ContextFactory;
+use crate::queue::JobQueue;
+use crate::worker::{ThreadConfig, ThreadHook};
+use crate::{PanicHandler, ShutdownPolicy, ThreadPool};
+
+/// Configures and creates a [`ThreadPool`].
+///
+/// ```
+/// use rust_server::ThreadPoolBuilder;
+///
+/// let pool = ThreadPoolBuilder::new()
+///     .num_threads(2)
+///     .thread_name("http")
+///     .on_thread_start(|id| println!("http-{id} started"))
+///     .build()
+///     .unwrap();
+/// pool.execute(|| println!("hello from the pool"));
+/// ```
+pub struct ThreadPoolBuilder {
+    num_threads: usize,
+    max_threads: Option<usize>,
+    keep_alive: Duration,
+    queue_capacity: Option<usize>,
+    thread_name: String,
+    stack_size: Option<usize>,
+    on_thread_start: Option<Arc<ThreadHook>>,
+    on_thread_stop: Option<Arc<ThreadHook>>,
+    panic_handler: Option<Arc<PanicHandler>>,
+    shutdown_policy: ShutdownPolicy,
+    cpu_affinity: Option<CpuAffinity>,
+    worker_context: Option<Arc<ContextFactory>>,
+}
+
+/// Why a [`ThreadPoolBuilder`] could not create a pool.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The pool was asked for zero worker threads.
+    ZeroThreads,
+    /// The maximum number of threads is below the core number.
+    MaxBelowCore { core: usize, max: usize },
+    /// A bounded queue was asked to hold zero jobs.
+    ZeroQueueCapacity,
+    /// The operating system refused to spawn a worker thread.
+    Spawn(io::Error),
+    /// The CPU affinity could not be applied: the platform doesn't support
+    /// it or a requested CPU is not available.
+    Affinity(io::Error),
+    /// The `on_thread_start` hook panicked on a worker, with this message.
+    StartHook(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::ZeroThreads => write!(f, "thread pool needs at least one thread"),
+            BuildError::MaxBelowCore { core, max } => {
+                write!(
+                    f,
+                    "maximum of {max} threads is below the {core} core threads"
+                )
+            }
+            BuildError::ZeroQueueCapacity => {
+                write!(f, "bounded queue needs room for at least one job")
+            }
+            BuildError::Spawn(err) => write!(f, "failed to spawn worker thread: {err}"),
+            BuildError::Affinity(err) => write!(f, "invalid CPU affinity: {err}"),
+            BuildError::StartHook(message) => {
+                write!(f, "thread start hook panicked: {message}")
+            }
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Spawn(err) | BuildError::Affinity(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        ThreadPoolBuilder::new()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// Start from one thread per available CPU, an unbounded queue and
+    /// threads named `worker-<id>`.
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            num_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            max_threads: None,
+            keep_alive: Duration::from_secs(60),
+            queue_capacity: None,
+            thread_name: "worker".to_string(),
+            stack_size: None,
+            on_thread_start: None,
+            on_thread_stop: None,
+            panic_handler: None,
+            shutdown_policy: ShutdownPolicy::Drain,
+            cpu_affinity: None,
+            worker_context: None,
+        }
+    }
+
+    /// Number of core worker threads, which stay alive even when idle.
+    pub fn num_threads(mut self, count: usize) -> ThreadPoolBuilder {
+        self.num_threads = count;
+        self
+    }
+
+    /// Let the pool grow up to `count` threads while jobs are waiting and
+    /// every worker is busy.
+    ///
+    /// Defaults to the core count, i.e. a fixed-size pool.
+    pub fn max_threads(mut self, count: usize) -> ThreadPoolBuilder {
+        self.max_threads = Some(count);
+        self
+    }
+
+    /// How long a thread above the core count may stay idle before it
+    /// exits. Defaults to 60 seconds.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> ThreadPoolBuilder {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Bound the job queue to `capacity` pending jobs.
+    ///
+    /// See [`ThreadPool::bounded`] for how a full queue behaves.
+    pub fn queue_capacity(mut self, capacity: usize) -> ThreadPoolBuilder {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Name worker threads `<prefix>-<id>`.
+    pub fn thread_name(mut self, prefix: impl Into<String>) -> ThreadPoolBuilder {
+        self.thread_name = prefix.into();
+        self
+    }
+
+    /// Stack size in bytes for each worker thread.
+    pub fn stack_size(mut self, size: usize) -> ThreadPoolBuilder {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Run `hook` on every worker thread before it takes its first job.
+    ///
+    /// The hook receives the worker id. It also runs for threads spawned to
+    /// replace a worker that died.
+    ///
+    /// If the hook panics while the pool is being built,
+    /// [`build`](ThreadPoolBuilder::build) fails with
+    /// [`BuildError::StartHook`]. On a thread started later the worker gives
+    /// up its slot instead of being replaced.
+    pub fn on_thread_start<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Run `hook` on every worker thread as it shuts down.
+    ///
+    /// The hook receives the worker id. It is skipped for a thread that dies
+    /// by panicking.
+    pub fn on_thread_stop<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_thread_stop = Some(Arc::new(hook));
+        self
+    }
+
+    /// Hook told about jobs that panic; see [`ThreadPool::set_panic_handler`].
+    pub fn panic_handler<H>(mut self, handler: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// What to do with queued jobs when the pool shuts down, either through
+    /// [`ThreadPool::shutdown`] or by being dropped. Defaults to
+    /// [`ShutdownPolicy::Drain`].
+    pub fn shutdown_policy(mut self, policy: ShutdownPolicy) -> ThreadPoolBuilder {
+        self.shutdown_policy = policy;
+        self
+    }
+
+    /// Pin each worker thread to a CPU, so it keeps its caches warm instead
+    /// of migrating between cores. Off by default; Linux only.
+    ///
+    /// [`ThreadPool::stats`] lists the CPU each worker is bound to.
+    pub fn cpu_affinity(mut self, affinity: CpuAffinity) -> ThreadPoolBuilder {
+        self.cpu_affinity = Some(affinity);
+        self
+    }
+
+    /// Give every worker thread a context built by `factory` when the
+    /// thread starts, for jobs started with [`ThreadPool::execute_with`] to
+    /// reuse, e.g. a buffer or a database connection.
+    ///
+    /// The factory receives the worker id and runs on the worker's own
+    /// thread, so the context doesn't need to be `Send`. It is dropped when
+    /// the thread exits.
+    pub fn worker_context<C, F>(mut self, factory: F) -> ThreadPoolBuilder
+    where
+        C: 'static,
+        F: Fn(usize) -> C + Send + Sync + 'static,
+    {
+        self.worker_context = Some(Arc::new(move |id| Box::new(factory(id))));
+        self
+    }
+
+    /// Spawn the worker threads and return the pool.
+    pub fn build(self) -> Result<ThreadPool, BuildError> {
+        let max_threads = self.max_threads.unwrap_or(self.num_threads);
+        check_sizes(self.num_threads, max_threads)?;
+        if self.queue_capacity == Some(0) {
+            return Err(BuildError::ZeroQueueCapacity);
+        }
+        let cpus = self
+            .cpu_affinity
+            .map(|affinity| affinity.resolve())
+            .transpose()
+            .map_err(BuildError::Affinity)?;
+        let thread_config = ThreadConfig {
+            name_prefix: self.thread_name,
+            stack_size: self.stack_size,
+            on_start: self.on_thread_start,
+            on_stop: self.on_thread_stop,
+            keep_alive: self.keep_alive,
+            cpus,
+            context: self.worker_context,
+        };
+        ThreadPool::start(
+            self.num_threads,
+            max_threads,
+            JobQueue::new(self.queue_capacity),
+            thread_config,
+            self.panic_handler,
+            self.shutdown_policy,
+        )
+    }
+}
+
+pub(crate) fn check_sizes(core: usize, max: usize) -> Result<(), BuildError> {
+    if core == 0 {
+        Err(BuildError::ZeroThreads)
+    } else if max < core {
+        Err(BuildError::MaxBelowCore { core, max })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Mutex};
+
+    #[test]
+    fn build_rejects_zero_threads() {
+        let err = ThreadPoolBuilder::new()
+            .num_threads(0)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, BuildError::ZeroThreads));
+    }
+
+    #[test]
+    fn build_rejects_max_below_core() {
+        let err = ThreadPoolBuilder::new()
+            .num_threads(4)
+            .max_threads(2)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, BuildError::MaxBelowCore { core: 4, max: 2 }));
+    }
+
+    #[test]
+    fn build_rejects_zero_capacity() {
+        let err = ThreadPoolBuilder::new()
+            .queue_capacity(0)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, BuildError::ZeroQueueCapacity));
+    }
+
+    #[test]
+    fn workers_are_named_and_hooks_run() {
+        let (start_tx, start_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let start_tx = Mutex::new(start_tx);
+        let stop_tx = Mutex::new(stop_tx);
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(2)
+            .thread_name("test-pool")
+            .stack_size(256 * 1024)
+            .on_thread_start(move |id| start_tx.lock().unwrap().send(id).unwrap())
+            .on_thread_stop(move |id| stop_tx.lock().unwrap().send(id).unwrap())
+            .build()
+            .unwrap();
+
+        let name = pool
+            .spawn(|| thread::current().name().map(str::to_string))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert!(name == "test-pool-0" || name == "test-pool-1");
+
+        drop(pool);
+        let mut started: Vec<_> = start_rx.iter().collect();
+        let mut stopped: Vec<_> = stop_rx.iter().collect();
+        started.sort();
+        stopped.sort();
+        assert_eq!(started, vec![0, 1]);
+        assert_eq!(stopped, vec![0, 1]);
+    }
+
+    #[test]
+    fn panicking_start_hook_fails_build_without_respawning() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook_calls = Arc::clone(&calls);
+        let err = ThreadPoolBuilder::new()
+            .num_threads(1)
+            .on_thread_start(move |_| {
+                hook_calls.fetch_add(1, Ordering::SeqCst);
+                panic!("no config");
+            })
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(&err, BuildError::StartHook(message) if message == "no config"));
+
+        // A respawning worker would run the hook again and again.
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::{worker, Shared, ThreadPool};
+
+/// Tells a job that it should stop, either because someone called
+/// [`cancel`](CancellationToken::cancel) or because its deadline passed.
+///
+/// Cancellation is cooperative: the job has to poll
+/// [`is_cancelled`](CancellationToken::is_cancelled) and return early.
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// A token without a deadline that is only cancelled explicitly.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// A token that counts as cancelled once `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> CancellationToken {
+        CancellationToken {
+            state: Arc::new(TokenState {
+                cancelled: AtomicBool::new(false),
+                deadline: Some(deadline),
+            }),
+        }
+    }
+
+    /// Ask the job to stop. A job that has not started yet is skipped.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the job should stop: the token was cancelled or its deadline
+    /// has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline()
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// When the token cancels itself, if it has a deadline.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.state.deadline
+    }
+}
+
+impl ThreadPool {
+    /// Queue `f` with a deadline `timeout` from now, counting time spent
+    /// in the queue.
+    ///
+    /// `f` gets the returned token to poll. If the deadline passes while `f`
+    /// is still running, the pool logs a warning with the worker id and
+    /// counts the job in [`PoolStats::overrun_jobs`](crate::PoolStats), even
+    /// if `f` never returns. A job whose deadline passes before it starts
+    /// is skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn execute_with_timeout<F>(&self, timeout: Duration, f: F) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        self.execute_with_token(
+            CancellationToken::with_deadline(Instant::now() + timeout),
+            f,
+        )
+    }
+
+    /// Queue `f` under `token`, which `f` gets to poll and the caller can
+    /// cancel through the returned clone. Several jobs may share a token.
+    ///
+    /// A job whose token is cancelled before it starts is skipped. Deadlines
+    /// are watched as described for
+    /// [`execute_with_timeout`](ThreadPool::execute_with_timeout).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn execute_with_token<F>(&self, token: CancellationToken, f: F) -> CancellationToken
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let job_token = token.clone();
+        let shared = Arc::downgrade(&self.shared);
+        self.execute(move || {
+            if job_token.is_cancelled() {
+                debug!("job was cancelled before it started");
+                return;
+            }
+            let _watch = job_token
+                .deadline()
+                .and_then(|deadline| DeadlineWatch::start(shared, deadline));
+            f(&job_token);
+        });
+        token
+    }
+}
+
+/// Reports a job that overruns its deadline exactly once: from the timer
+/// thread if it is still running then, or when it finishes late.
+///
+/// Dropping the watch takes its check off the timer.
+struct DeadlineWatch {
+    shared: Weak<Shared>,
+    worker: usize,
+    deadline: Instant,
+    reported: Arc<AtomicBool>,
+    /// Cancels the check on the timer thread.
+    unwatch: Arc<AtomicBool>,
+}
+
+impl DeadlineWatch {
+    fn start(shared: Weak<Shared>, deadline: Instant) -> Option<DeadlineWatch> {
+        let worker = worker::current_id().expect("jobs run on worker threads");
+        let reported = Arc::new(AtomicBool::new(false));
+        let check = {
+            let (shared, reported) = (shared.clone(), Arc::clone(&reported));
+            Box::new(move || {
+                if !reported.swap(true, Ordering::SeqCst) {
+                    if let Some(shared) = shared.upgrade() {
+                        shared.metrics.record_overrun();
+                        warn!(worker = worker; "job is still running past its deadline");
+                    }
+                }
+            })
+        };
+        let unwatch = shared.upgrade()?.watch(deadline, check);
+        Some(DeadlineWatch {
+            shared,
+            worker,
+            deadline,
+            reported,
+            unwatch,
+        })
+    }
+}
+
+impl Drop for DeadlineWatch {
+    fn drop(&mut self) {
+        let Some(shared) = self.shared.upgrade() else {
+            return;
+        };
+        shared.unwatch(&self.unwatch);
+        let overrun = Instant::now().saturating_duration_since(self.deadline);
+        if overrun.is_zero() || self.reported.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        shared.metrics.record_overrun();
+        warn!(worker = self.worker, overrun:? = overrun; "job finished past its deadline");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn cancelled_job_is_skipped_and_running_job_sees_cancel() {
+        let pool = ThreadPool::new(1);
+        let (started_tx, started_rx) = mpsc::channel();
+        let (stopped_tx, stopped_rx) = mpsc::channel();
+        let running = pool.execute_with_token(CancellationToken::new(), move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            stopped_tx.send(()).unwrap();
+        });
+        let (ran_tx, ran_rx) = mpsc::channel();
+        let queued = pool.execute_with_token(CancellationToken::new(), move |_| {
+            ran_tx.send(()).unwrap();
+        });
+        queued.cancel();
+
+        started_rx.recv().unwrap();
+        running.cancel();
+        stopped_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        pool.shutdown(Duration::from_secs(5));
+        assert!(ran_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn overrunning_job_is_reported_while_running() {
+        let pool = ThreadPool::new(1);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let token = pool.execute_with_timeout(Duration::from_millis(20), move |_| {
+            // Ignores its token, like a runaway handler would.
+            let _ = release_rx.recv();
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.stats().overrun_jobs == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(pool.stats().overrun_jobs, 1);
+        assert!(token.is_cancelled());
+
+        // Finishing late afterwards does not count a second time.
+        drop(release_tx);
+        pool.shutdown(Duration::from_secs(5));
+        assert_eq!(pool.stats().overrun_jobs, 1);
+    }
+
+    #[test]
+    fn job_within_deadline_is_not_reported() {
+        let pool = ThreadPool::new(1);
+        pool.execute_with_timeout(Duration::from_secs(5), |token| {
+            assert!(!token.is_cancelled());
+        });
+        pool.shutdown(Duration::from_secs(5));
+        assert_eq!(pool.stats().overrun_jobs, 0);
+    }
+}
@@ -0,0 +1,139 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+use log::error;
+
+use crate::{panic_message, worker, ThreadPool};
+
+/// Builds the context of a worker from its id, on the worker's thread.
+pub(crate) type ContextFactory = dyn Fn(usize) -> Box<dyn Any> + Send + Sync + 'static;
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+}
+
+/// What a job started with [`ThreadPool::execute_with`] knows about the
+/// worker running it.
+pub struct WorkerContext<'a> {
+    id: usize,
+    data: Option<&'a mut dyn Any>,
+}
+
+impl WorkerContext<'_> {
+    /// Id of the worker, as passed to the thread hooks and shown in thread
+    /// names.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The value the worker's context factory built, if there is one of
+    /// type `T`; see
+    /// [`ThreadPoolBuilder::worker_context`](crate::ThreadPoolBuilder::worker_context).
+    ///
+    /// It lives as long as the worker thread, so jobs can reuse buffers or
+    /// connections in it.
+    pub fn data<T: Any>(&mut self) -> Option<&mut T> {
+        self.data.as_deref_mut()?.downcast_mut()
+    }
+}
+
+/// Build this worker thread's context. A factory that panics leaves the
+/// worker without one instead of killing it.
+pub(crate) fn init(id: usize, factory: &ContextFactory) {
+    match panic::catch_unwind(AssertUnwindSafe(|| factory(id))) {
+        Ok(data) => CONTEXT.with(|context| *context.borrow_mut() = Some(data)),
+        Err(payload) => error!(
+            worker = id;
+            "worker context factory panicked: {}", panic_message(payload.as_ref())
+        ),
+    }
+}
+
+impl ThreadPool {
+    /// Queue `f` to run on one of the workers with that worker's
+    /// [`WorkerContext`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn execute_with<F>(&self, f: F)
+    where
+        F: FnOnce(&mut WorkerContext<'_>) + Send + 'static,
+    {
+        self.execute(move || {
+            let id = worker::current_id().expect("jobs run on worker threads");
+            CONTEXT.with(|context| {
+                let mut data = context.borrow_mut();
+                f(&mut WorkerContext {
+                    id,
+                    data: data.as_deref_mut(),
+                })
+            })
+        });
+    }
+
+    /// Id of the worker the calling code runs on, or `None` outside of a
+    /// worker thread.
+    pub fn current_worker_id() -> Option<usize> {
+        worker::current_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// Per-worker scratch space, tagged with the worker it belongs to.
+    struct Scratch {
+        owner: usize,
+        jobs: usize,
+    }
+
+    #[test]
+    fn jobs_reuse_their_workers_context() {
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .worker_context(|id| Scratch { owner: id, jobs: 0 })
+            .build()
+            .unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        for _ in 0..20 {
+            let done_tx = done_tx.clone();
+            pool.execute_with(move |ctx| {
+                assert_eq!(Some(ctx.id()), ThreadPool::current_worker_id());
+                assert!(ctx.data::<String>().is_none());
+                let id = ctx.id();
+                let scratch = ctx.data::<Scratch>().unwrap();
+                assert_eq!(scratch.owner, id);
+                scratch.jobs += 1;
+                done_tx.send((id, scratch.jobs)).unwrap();
+            });
+        }
+        let mut seen: Vec<_> = done_rx.iter().take(20).collect();
+        seen.sort_unstable();
+        // Every worker counts its own jobs from 1 upwards.
+        for id in [0, 1] {
+            let counts: Vec<_> = seen
+                .iter()
+                .filter(|&&(worker, _)| worker == id)
+                .map(|&(_, jobs)| jobs)
+                .collect();
+            assert_eq!(counts, (1..=counts.len()).collect::<Vec<_>>());
+        }
+        assert_eq!(ThreadPool::current_worker_id(), None);
+    }
+
+    #[test]
+    fn failing_factory_leaves_worker_without_context() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .worker_context(|_| -> u32 { panic!("no database") })
+            .build()
+            .unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.execute_with(move |ctx| done_tx.send(ctx.data::<u32>().is_none()).unwrap());
+        assert!(done_rx.recv().unwrap());
+    }
+}
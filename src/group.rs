@@ -0,0 +1,231 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use crate::{panic_message, CancellationToken, JobHandle, ThreadPool};
+
+/// Why a job of a [`JobGroup`] produced no value.
+pub enum JobError<E> {
+    /// The job returned this error.
+    Failed(E),
+    /// The job panicked with this payload.
+    Panicked(Box<dyn Any + Send>),
+    /// The group was cancelled before the job started.
+    Cancelled,
+}
+
+impl<E: fmt::Debug> fmt::Debug for JobError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Failed(err) => f.debug_tuple("Failed").field(err).finish(),
+            JobError::Panicked(payload) => f
+                .debug_tuple("Panicked")
+                .field(&panic_message(payload.as_ref()))
+                .finish(),
+            JobError::Cancelled => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for JobError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Failed(err) => write!(f, "job failed: {err}"),
+            JobError::Panicked(payload) => {
+                write!(f, "job panicked: {}", panic_message(payload.as_ref()))
+            }
+            JobError::Cancelled => f.write_str("job was cancelled before it started"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for JobError<E> {}
+
+/// A set of related jobs that are awaited together.
+///
+/// Every job gets the group's [`CancellationToken`]. With
+/// [`cancel_on_failure`](JobGroup::cancel_on_failure), the first job that
+/// fails or panics cancels the token: jobs that have not started yet are
+/// skipped and running ones can poll the token to stop early.
+///
+/// ```
+/// let pool = rust_server::ThreadPool::new(2);
+/// let mut group = pool.group().cancel_on_failure();
+/// for name in ["a.html", "b.html"] {
+///     group.spawn(move |_| Ok::<_, String>(name.len()));
+/// }
+/// assert_eq!(group.join().unwrap(), [6, 6]);
+/// ```
+pub struct JobGroup<'pool, T, E> {
+    pool: &'pool ThreadPool,
+    handles: Vec<JobHandle<Result<T, JobError<E>>>>,
+    token: CancellationToken,
+    cancel_on_failure: bool,
+    /// Index of the job that failed first, in completion order.
+    first_failure: Arc<Mutex<Option<usize>>>,
+}
+
+impl<'pool, T, E> JobGroup<'pool, T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    pub(crate) fn new(pool: &'pool ThreadPool) -> JobGroup<'pool, T, E> {
+        JobGroup {
+            pool,
+            handles: Vec::new(),
+            token: CancellationToken::new(),
+            cancel_on_failure: false,
+            first_failure: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Cancel the rest of the group as soon as one job fails or panics.
+    pub fn cancel_on_failure(mut self) -> JobGroup<'pool, T, E> {
+        self.cancel_on_failure = true;
+        self
+    }
+
+    /// Add a job to the group and queue it on the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn spawn<F>(&mut self, f: F)
+    where
+        F: FnOnce(&CancellationToken) -> Result<T, E> + Send + 'static,
+    {
+        let index = self.handles.len();
+        let group_token = self.token.clone();
+        let cancel_on_failure = self.cancel_on_failure;
+        let first_failure = Arc::clone(&self.first_failure);
+        let failed = move || {
+            first_failure.lock().unwrap().get_or_insert(index);
+            if cancel_on_failure {
+                group_token.cancel();
+            }
+        };
+        let token = self.token.clone();
+        self.handles.push(self.pool.spawn(move || {
+            if token.is_cancelled() {
+                return Err(JobError::Cancelled);
+            }
+            match panic::catch_unwind(AssertUnwindSafe(|| f(&token))) {
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(err)) => {
+                    failed();
+                    Err(JobError::Failed(err))
+                }
+                Err(payload) => {
+                    failed();
+                    panic::resume_unwind(payload)
+                }
+            }
+        }));
+    }
+
+    /// Cancel the group: jobs that have not started are skipped and the
+    /// running ones see their token cancelled.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Number of jobs added so far.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Whether no job has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Wait for every job and return their outcomes in the order the jobs
+    /// were added.
+    pub fn join_all(self) -> Vec<Result<T, JobError<E>>> {
+        self.handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|payload| Err(JobError::Panicked(payload)))
+            })
+            .collect()
+    }
+
+    /// Wait for every job and return all values in the order the jobs were
+    /// added, or the error of the job that failed first.
+    pub fn join(self) -> Result<Vec<T>, JobError<E>> {
+        let first_failure = Arc::clone(&self.first_failure);
+        let mut results = self.join_all();
+        if let Some(index) = *first_failure.lock().unwrap() {
+            return Err(results
+                .swap_remove(index)
+                .err()
+                .expect("failed job has an error"));
+        }
+        results.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn join_collects_values_in_order() {
+        let pool = ThreadPool::new(3);
+        let mut group = pool.group();
+        for n in 0..10u64 {
+            group.spawn(move |_| {
+                std::thread::sleep(Duration::from_millis(10 - n));
+                Ok::<_, ()>(n * n)
+            });
+        }
+        assert_eq!(group.len(), 10);
+        assert_eq!(
+            group.join().unwrap(),
+            (0..10).map(|n| n * n).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn first_failure_cancels_the_rest() {
+        let pool = ThreadPool::new(1);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let mut group = pool.group().cancel_on_failure();
+        group.spawn(move |_| {
+            release_rx.recv().unwrap();
+            Err("disk full")
+        });
+        group.spawn(|_| Ok(1));
+        group.spawn(|_| Err("never runs"));
+        release_tx.send(()).unwrap();
+
+        let results = group.join_all();
+        assert!(matches!(results[0], Err(JobError::Failed("disk full"))));
+        assert!(matches!(results[1], Err(JobError::Cancelled)));
+        assert!(matches!(results[2], Err(JobError::Cancelled)));
+    }
+
+    #[test]
+    fn join_reports_panics_and_keeps_going_without_cancel() {
+        let pool = ThreadPool::new(1);
+        let mut group = pool.group::<u32, String>();
+        group.spawn(|_| panic!("warming failed"));
+        group.spawn(|token| {
+            assert!(!token.is_cancelled());
+            Ok(2)
+        });
+        match group.join() {
+            Err(err @ JobError::Panicked(_)) => {
+                assert_eq!(err.to_string(), "job panicked: warming failed")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Owned permission to wait for the result of a job started with
+/// [`ThreadPool::spawn`](crate::ThreadPool::spawn) or
+/// [`ThreadPool::spawn_future`](crate::ThreadPool::spawn_future).
+///
+/// The handle can be joined from a thread or awaited from async code.
+/// Dropping it detaches the job; it still runs, but its result is thrown
+/// away.
+pub struct JobHandle<T> {
+    packet: Arc<Packet<T>>,
+}
+
+/// Where the worker leaves the job's result for the handle to pick up.
+struct Packet<T> {
+    result: Mutex<Option<thread::Result<T>>>,
+    done: Condvar,
+    /// Task awaiting the handle, woken once `result` is filled in.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl<T> Packet<T> {
+    fn notify(&self) {
+        self.done.notify_all();
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Worker-side half of a [`JobHandle`].
+pub(crate) struct Completer<T> {
+    packet: Arc<Packet<T>>,
+}
+
+pub(crate) fn pair<T>() -> (Completer<T>, JobHandle<T>) {
+    let packet = Arc::new(Packet {
+        result: Mutex::new(None),
+        done: Condvar::new(),
+        waker: Mutex::new(None),
+    });
+    (
+        Completer {
+            packet: Arc::clone(&packet),
+        },
+        JobHandle { packet },
+    )
+}
+
+impl<T> Completer<T> {
+    pub(crate) fn complete(self, result: thread::Result<T>) {
+        *self.packet.result.lock().unwrap() = Some(result);
+        self.packet.notify();
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    /// A job that is dropped without running, e.g. because the pool shut
+    /// down and discarded its queue, must not leave the handle waiting
+    /// forever.
+    fn drop(&mut self) {
+        let mut result = self.packet.result.lock().unwrap();
+        if result.is_none() {
+            *result = Some(Err(Box::new("job was dropped before it finished")));
+            drop(result);
+            self.packet.notify();
+        }
+    }
+}
+
+impl<T> JobHandle<T> {
+    /// Wait for the job to finish.
+    ///
+    /// Returns the job's value, or the payload it panicked with.
+    pub fn join(self) -> thread::Result<T> {
+        let mut result = self.packet.result.lock().unwrap();
+        loop {
+            if let Some(result) = result.take() {
+                return result;
+            }
+            result = self.packet.done.wait(result).unwrap();
+        }
+    }
+
+    /// Collect the result if the job has already finished.
+    ///
+    /// Gives the handle back if it is still queued or running.
+    pub fn try_join(self) -> Result<thread::Result<T>, JobHandle<T>> {
+        let result = self.packet.result.lock().unwrap().take();
+        result.ok_or(self)
+    }
+
+    /// Wait at most `timeout` for the job to finish.
+    ///
+    /// Gives the handle back if the job is still queued or running when the
+    /// timeout expires.
+    pub fn join_timeout(self, timeout: Duration) -> Result<thread::Result<T>, JobHandle<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut result = self.packet.result.lock().unwrap();
+        loop {
+            if let Some(result) = result.take() {
+                return Ok(result);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                drop(result);
+                return Err(self);
+            }
+            result = self
+                .packet
+                .done
+                .wait_timeout(result, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Whether the job has finished, successfully or by panicking.
+    pub fn is_finished(&self) -> bool {
+        self.packet.result.lock().unwrap().is_some()
+    }
+}
+
+impl<T> Future for JobHandle<T> {
+    type Output = thread::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<thread::Result<T>> {
+        let mut result = self.packet.result.lock().unwrap();
+        if let Some(result) = result.take() {
+            return Poll::Ready(result);
+        }
+        // Still holding `result`, so the completer cannot slip in between
+        // the check and registering the waker.
+        *self.packet.waker.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::sync::mpsc;
+
+    #[test]
+    fn join_returns_value() {
+        let pool = ThreadPool::new(2);
+        let handle = pool.spawn(|| 6 * 7);
+        assert_eq!(handle.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn join_returns_panic_payload() {
+        let pool = ThreadPool::new(1);
+        let handle = pool.spawn(|| -> u32 { panic!("boom") });
+        let payload = handle.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+
+        // The worker survives the panic and keeps serving.
+        assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+    }
+
+    #[test]
+    fn try_join_and_join_timeout_give_handle_back() {
+        let pool = ThreadPool::new(1);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let handle = pool.spawn(move || {
+            release_rx.recv().unwrap();
+            "done"
+        });
+
+        let handle = handle.try_join().unwrap_err();
+        let handle = handle.join_timeout(Duration::from_millis(20)).unwrap_err();
+        assert!(!handle.is_finished());
+
+        release_tx.send(()).unwrap();
+        let result = handle.join_timeout(Duration::from_secs(5)).ok().unwrap();
+        assert_eq!(result.unwrap(), "done");
+    }
+}
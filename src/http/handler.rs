@@ -0,0 +1,33 @@
+use super::{Request, Response};
+
+/// Turns a request into a response. Implemented for closures taking a
+/// [`Request`] and for [`Router`](super::Router).
+///
+/// Handlers run on the server's worker threads, several at a time. A
+/// handler that panics gets its client a 500 and its connection closed.
+///
+/// ```
+/// use rust_server::{Handler, Request, Response};
+///
+/// fn hello(request: Request) -> Response {
+///     Response::builder()
+///         .body(format!("hello from {}", request.path()))
+///         .build()
+/// }
+///
+/// fn assert_handler(_: impl Handler) {}
+/// assert_handler(hello);
+/// assert_handler(|_: Request| Response::new(204));
+/// ```
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: Request) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request) -> Response {
+        self(request)
+    }
+}
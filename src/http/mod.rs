@@ -0,0 +1,53 @@
+//! HTTP/1.x protocol support for the server.
+//!
+//! A [`Server`] accepts connections and answers each request with a
+//! [`Handler`], such as a closure or a [`Router`] that picks one by method
+//! and path.
+//!
+//! Underneath, [`read_request`] parses one request off a connection into a
+//! [`Request`]. Input that is not valid HTTP yields a [`ParseError`]
+//! carrying the status code to answer with.
+
+mod handler;
+mod parse;
+mod request;
+mod response;
+mod router;
+mod server;
+
+pub use handler::Handler;
+pub use parse::{read_request, ParseError};
+pub use request::{Headers, Method, Request, Version};
+pub use response::{Response, ResponseBuilder};
+pub use router::{Routed, Router};
+pub use server::{Server, ServerBuilder};
+
+/// Standard reason phrase for `status`, or an empty string for codes this
+/// server doesn't know.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
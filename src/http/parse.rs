@@ -0,0 +1,529 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+use super::{Headers, Method, Request, Version};
+
+/// Longest request line or header line accepted, including the line ending.
+const MAX_LINE: usize = 8 * 1024;
+/// Most header fields accepted in one request.
+const MAX_HEADERS: usize = 100;
+
+/// Why a request could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading from the connection failed, e.g. because it timed out.
+    Io(io::Error),
+    /// The request is not valid HTTP/1.x.
+    BadRequest(&'static str),
+    /// The request target is longer than the server accepts.
+    UriTooLong,
+    /// A header line, or the number of headers, is over the limit.
+    HeadersTooLarge,
+    /// The request uses an HTTP version other than 1.0 or 1.1.
+    VersionNotSupported,
+    /// The body is longer than the limit passed to [`read_request`].
+    BodyTooLarge,
+    /// The body is sent with a transfer coding other than `chunked`.
+    UnsupportedTransferCoding,
+}
+
+impl ParseError {
+    /// Status code to answer with, or `None` when the connection itself
+    /// failed and there is nobody left to answer.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ParseError::Io(_) => None,
+            ParseError::BadRequest(_) => Some(400),
+            ParseError::UriTooLong => Some(414),
+            ParseError::HeadersTooLarge => Some(431),
+            ParseError::VersionNotSupported => Some(505),
+            ParseError::BodyTooLarge => Some(413),
+            ParseError::UnsupportedTransferCoding => Some(501),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "failed to read request: {err}"),
+            ParseError::BadRequest(reason) => write!(f, "malformed request: {reason}"),
+            ParseError::UriTooLong => write!(f, "request target is too long"),
+            ParseError::HeadersTooLarge => write!(f, "request headers are too large"),
+            ParseError::VersionNotSupported => write!(f, "unsupported HTTP version"),
+            ParseError::BodyTooLarge => write!(f, "request body is too large"),
+            ParseError::UnsupportedTransferCoding => write!(f, "unsupported transfer coding"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+/// Read one request from `reader`, including a body of at most `max_body`
+/// bytes.
+///
+/// Returns `Ok(None)` if the connection was closed before the request
+/// started. Empty lines in front of the request line are skipped. Bytes
+/// after the request are left in `reader` for the next one.
+pub fn read_request<R: BufRead>(
+    reader: &mut R,
+    max_body: usize,
+) -> Result<Option<Request>, ParseError> {
+    let line = loop {
+        match read_line(reader, ParseError::UriTooLong)? {
+            None => return Ok(None),
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+    let (method, target, version) = parse_request_line(&line)?;
+    let (path, query) = split_target(&method, target)?;
+
+    let mut headers = Headers::new();
+    read_fields(reader, &mut headers, MAX_HEADERS)?;
+    if version == Version::Http11 && headers.get_all("Host").count() != 1 {
+        return Err(ParseError::BadRequest(
+            "HTTP/1.1 requires exactly one Host header",
+        ));
+    }
+
+    let mut trailers = Headers::new();
+    let body = match body_framing(&headers, version)? {
+        Framing::Chunked => {
+            let body = read_chunked(reader, max_body)?;
+            read_fields(reader, &mut trailers, MAX_HEADERS - headers.len())?;
+            body
+        }
+        Framing::Length(length) => read_sized(reader, length, max_body)?,
+    };
+
+    Ok(Some(Request {
+        method,
+        target: target.to_string(),
+        path,
+        query,
+        version,
+        headers,
+        body,
+        trailers,
+        params: Vec::new(),
+    }))
+}
+
+/// Read header fields into `fields` up to the empty line that ends them,
+/// failing if there are more than `max`.
+fn read_fields<R: BufRead>(
+    reader: &mut R,
+    fields: &mut Headers,
+    max: usize,
+) -> Result<(), ParseError> {
+    let mut count = 0;
+    loop {
+        let line = read_line(reader, ParseError::HeadersTooLarge)?.ok_or(
+            ParseError::BadRequest("connection closed inside the headers"),
+        )?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        if count == max {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        let (name, value) = parse_header(&line)?;
+        fields.append(name, value);
+        count += 1;
+    }
+}
+
+/// How the end of the body is found.
+enum Framing {
+    Chunked,
+    Length(usize),
+}
+
+/// Work out the body framing as RFC 9112 section 6.3 prescribes. Requests
+/// that carry both `Transfer-Encoding` and `Content-Length` are refused
+/// outright, as they are the usual vehicle for request smuggling.
+fn body_framing(headers: &Headers, version: Version) -> Result<Framing, ParseError> {
+    if headers.contains("Transfer-Encoding") {
+        if headers.contains("Content-Length") {
+            return Err(ParseError::BadRequest(
+                "both Transfer-Encoding and Content-Length",
+            ));
+        }
+        if version == Version::Http10 {
+            return Err(ParseError::BadRequest("Transfer-Encoding in HTTP/1.0"));
+        }
+        let mut codings = headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|coding| !coding.is_empty());
+        return match (codings.next(), codings.next()) {
+            (Some(coding), None) if coding.eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked),
+            _ => Err(ParseError::UnsupportedTransferCoding),
+        };
+    }
+    // Repeated lengths are allowed as long as they all agree.
+    let mut length = None;
+    for value in headers
+        .get_all("Content-Length")
+        .flat_map(|value| value.split(','))
+    {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::BadRequest("invalid Content-Length"));
+        }
+        // Too many digits for a usize is certainly too large a body.
+        let parsed = value.parse().unwrap_or(usize::MAX);
+        if length.is_some_and(|length| length != parsed) {
+            return Err(ParseError::BadRequest("conflicting Content-Length"));
+        }
+        length = Some(parsed);
+    }
+    Ok(Framing::Length(length.unwrap_or(0)))
+}
+
+fn read_sized<R: BufRead>(
+    reader: &mut R,
+    length: usize,
+    max_body: usize,
+) -> Result<Vec<u8>, ParseError> {
+    if length > max_body {
+        return Err(ParseError::BodyTooLarge);
+    }
+    let mut body = Vec::with_capacity(length);
+    reader.by_ref().take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(ParseError::BadRequest("connection closed inside the body"));
+    }
+    Ok(body)
+}
+
+/// Read a chunked body up to and including its last, empty chunk. The
+/// trailer fields that follow are left in `reader`.
+fn read_chunked<R: BufRead>(reader: &mut R, max_body: usize) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(
+            reader,
+            ParseError::BadRequest("chunk size line is too long"),
+        )?
+        .ok_or(ParseError::BadRequest("connection closed inside the body"))?;
+        // Chunk extensions are allowed but mean nothing to us.
+        let size = line.split(';').next().unwrap_or_default().trim_end();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::BadRequest("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16).unwrap_or(usize::MAX);
+        if size == 0 {
+            return Ok(body);
+        }
+        if size > max_body - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+        body.append(&mut read_sized(reader, size, size)?);
+        match read_line(
+            reader,
+            ParseError::BadRequest("chunk is longer than its size"),
+        )? {
+            Some(rest) if rest.is_empty() => {}
+            Some(_) => return Err(ParseError::BadRequest("chunk is longer than its size")),
+            None => return Err(ParseError::BadRequest("connection closed inside the body")),
+        }
+    }
+}
+
+/// Read a line ending in CRLF (or a bare LF) and return it without the
+/// ending. `Ok(None)` means end of input before the first byte; a line
+/// longer than `MAX_LINE` fails with `too_long`.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    too_long: ParseError,
+) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(if read > MAX_LINE {
+            too_long
+        } else {
+            ParseError::BadRequest("connection closed inside a line")
+        });
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::BadRequest("line is not valid UTF-8"))
+}
+
+fn parse_request_line(line: &str) -> Result<(Method, &str, Version), ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::BadRequest(
+            "request line is not `METHOD TARGET VERSION`",
+        ));
+    };
+    if !is_token(method) {
+        return Err(ParseError::BadRequest("invalid method"));
+    }
+    if target.is_empty() || !target.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(ParseError::BadRequest("invalid request target"));
+    }
+    Ok((Method::from_token(method), target, parse_version(version)?))
+}
+
+fn parse_version(version: &str) -> Result<Version, ParseError> {
+    match version.strip_prefix("HTTP/").map(str::as_bytes) {
+        Some(b"1.1") => Ok(Version::Http11),
+        Some(b"1.0") => Ok(Version::Http10),
+        Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            Err(ParseError::VersionNotSupported)
+        }
+        _ => Err(ParseError::BadRequest("invalid HTTP version")),
+    }
+}
+
+/// Split the target into path and query. Besides the usual `/path?query`,
+/// this accepts absolute URLs as sent to proxies, and `*` for `OPTIONS`.
+fn split_target(method: &Method, target: &str) -> Result<(String, Option<String>), ParseError> {
+    let target = match target.find("://") {
+        Some(scheme_end) if !target.starts_with('/') => {
+            let rest = &target[scheme_end + 3..];
+            match rest.find(['/', '?']) {
+                Some(path_start) if rest.as_bytes()[path_start] == b'/' => &rest[path_start..],
+                // A URL without a path asks for `/`.
+                Some(path_start) => return Ok(("/".into(), Some(rest[path_start + 1..].into()))),
+                None => "/",
+            }
+        }
+        _ if target == "*" && *method == Method::Options => return Ok(("*".into(), None)),
+        _ if target.starts_with('/') => target,
+        _ => return Err(ParseError::BadRequest("invalid request target")),
+    };
+    let target = target.split('#').next().unwrap_or_default();
+    Ok(match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    })
+}
+
+fn parse_header(line: &str) -> Result<(&str, &str), ParseError> {
+    if line.starts_with([' ', '\t']) {
+        // Obsolete line folding; RFC 9112 lets servers reject it.
+        return Err(ParseError::BadRequest("folded header line"));
+    }
+    let (name, value) = line
+        .split_once(':')
+        .ok_or(ParseError::BadRequest("header line without a colon"))?;
+    if !is_token(name) {
+        return Err(ParseError::BadRequest("invalid header name"));
+    }
+    let value = value.trim_matches([' ', '\t']);
+    if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+        return Err(ParseError::BadRequest("control character in header value"));
+    }
+    Ok((name, value))
+}
+
+/// Whether `s` is a non-empty `token` as defined by RFC 9110.
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Option<Request>, ParseError> {
+        read_request(&mut input.as_bytes(), 64)
+    }
+
+    fn status_of(input: &str) -> Option<u16> {
+        parse(input).unwrap_err().status()
+    }
+
+    #[test]
+    fn parses_request_line_and_headers() {
+        let request = parse(
+            "\r\nGET /search?q=rust&page=2 HTTP/1.1\r\n\
+             Host: example.com\r\n\
+             User-Agent:  curl/8.0 \r\n\
+             accept: */*\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(*request.method(), Method::Get);
+        assert_eq!(request.target(), "/search?q=rust&page=2");
+        assert_eq!(request.path(), "/search");
+        assert_eq!(request.query(), Some("q=rust&page=2"));
+        assert_eq!(request.version(), Version::Http11);
+        assert_eq!(request.header("user-agent"), Some("curl/8.0"));
+        assert_eq!(request.header("Accept"), Some("*/*"));
+        assert_eq!(request.headers().len(), 3);
+        assert!(request.body().is_empty());
+    }
+
+    #[test]
+    fn accepts_other_target_forms() {
+        let request = parse("GET http://example.com/a/b?c HTTP/1.0\n\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.version(), Version::Http10);
+        assert_eq!((request.path(), request.query()), ("/a/b", Some("c")));
+
+        let request = parse("OPTIONS * HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.path(), "*");
+
+        let request = parse("PURGE /cache HTTP/1.0\r\n\r\n").unwrap().unwrap();
+        assert_eq!(*request.method(), Method::Other("PURGE".into()));
+    }
+
+    #[test]
+    fn leaves_following_requests_in_the_reader() {
+        let mut input = "GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n".as_bytes();
+        let first = read_request(&mut input, 0).unwrap().unwrap();
+        let second = read_request(&mut input, 0).unwrap().unwrap();
+        assert_eq!((first.path(), second.path()), ("/a", "/b"));
+        assert!(read_request(&mut input, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn keep_alive_follows_version_and_connection_header() {
+        for (input, keep_alive) in [
+            ("GET / HTTP/1.1\r\nHost: x\r\n\r\n", true),
+            (
+                "GET / HTTP/1.1\r\nHost: x\r\nConnection: Close\r\n\r\n",
+                false,
+            ),
+            ("GET / HTTP/1.0\r\n\r\n", false),
+            ("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n", true),
+        ] {
+            let request = parse(input).unwrap().unwrap();
+            assert_eq!(request.keep_alive(), keep_alive, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn reads_sized_bodies() {
+        let mut input = "POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello\
+                         GET /b HTTP/1.1\r\nHost: x\r\nContent-Length: 0, 0\r\n\r\n"
+            .as_bytes();
+        let first = read_request(&mut input, 5).unwrap().unwrap();
+        assert_eq!(first.body(), b"hello");
+        let second = read_request(&mut input, 5).unwrap().unwrap();
+        assert_eq!((second.path(), second.body()), ("/b", &b""[..]));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn reads_chunked_bodies_and_trailers() {
+        let mut input = "POST /upload HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: Chunked\r\n\r\n\
+                         5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\n\
+                         Checksum: abc\r\n\r\n\
+                         GET / HTTP/1.0\r\n\r\n"
+            .as_bytes();
+        let request = read_request(&mut input, 12).unwrap().unwrap();
+        assert_eq!(request.body(), b"hello, world");
+        assert_eq!(request.trailers().get("checksum"), Some("abc"));
+        assert_eq!(request.header("Checksum"), None);
+        let next = read_request(&mut input, 12).unwrap().unwrap();
+        assert_eq!(next.version(), Version::Http10);
+    }
+
+    #[test]
+    fn enforces_the_body_limit() {
+        let sized = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 65\r\n\r\n";
+        assert_eq!(status_of(sized), Some(413));
+        let huge = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 99999999999999999999999\r\n\r\n";
+        assert_eq!(status_of(huge), Some(413));
+        let chunked = format!(
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+             20\r\n{0}\r\n20\r\n{0}\r\n1\r\na\r\n0\r\n\r\n",
+            "a".repeat(32)
+        );
+        assert_eq!(status_of(&chunked), Some(413));
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        for input in [
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhel",
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: -1\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab",
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n",
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n",
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n",
+        ] {
+            assert_eq!(status_of(input), Some(400), "{input:?}");
+        }
+        let gzip = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip, chunked\r\n\r\n";
+        assert_eq!(status_of(gzip), Some(501));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        for input in [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\nHost: x\r\n\r\n",
+            "GET / HTTP/1.1 extra\r\nHost: x\r\n\r\n",
+            "G(T / HTTP/1.1\r\nHost: x\r\n\r\n",
+            "GET index.html HTTP/1.1\r\nHost: x\r\n\r\n",
+            "GET / HTTX/1.1\r\nHost: x\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nHost: y\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nBroken\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost : x\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nX-A: a\r\n  folded\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nX-A: a\x07\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\n",
+        ] {
+            assert_eq!(status_of(input), Some(400), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_requests_and_other_versions() {
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status_of(&long_target), Some(414));
+
+        let long_header = format!("GET / HTTP/1.1\r\nX-A: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status_of(&long_header), Some(431));
+
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\nHost: x\r\n{}\r\n",
+            "X-A: a\r\n".repeat(MAX_HEADERS)
+        );
+        assert_eq!(status_of(&many_headers), Some(431));
+
+        assert_eq!(status_of("GET / HTTP/2.0\r\n\r\n"), Some(505));
+    }
+}
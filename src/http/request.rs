@@ -0,0 +1,284 @@
+use std::fmt;
+
+use super::parse::is_token;
+
+/// Request method. Methods without a variant of their own are kept
+/// verbatim in [`Method::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    Connect,
+    Trace,
+    Other(String),
+}
+
+impl Method {
+    /// Method names are case-sensitive, so `get` is an unknown method, not
+    /// `GET`.
+    pub(crate) fn from_token(token: &str) -> Method {
+        match token {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            "CONNECT" => Method::Connect,
+            "TRACE" => Method::Trace,
+            other => Method::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+            Method::Connect => "CONNECT",
+            Method::Trace => "TRACE",
+            Method::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Protocol version of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        })
+    }
+}
+
+/// Header fields in the order they were received. Lookups ignore the case
+/// of the name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Headers {
+        Headers::default()
+    }
+
+    /// Value of the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of every field called `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Whether any `name` field lists `token` among its comma-separated
+    /// values, ignoring case, as in `Connection: keep-alive, Upgrade`.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Add a field, keeping any existing ones of the same name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid field name or `value` holds a
+    /// control character other than tab, such as CR or LF, which would let
+    /// the field break out of its line.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let field = checked_field(name.into(), value.into());
+        self.fields.push(field);
+    }
+
+    /// Replace every field called `name` with a single one.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`append`](Headers::append).
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = checked_field(name.into(), value.into());
+        self.remove(&name);
+        self.fields.push((name, value));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.fields
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    /// Every field as `(name, value)`, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+fn checked_field(name: String, value: String) -> (String, String) {
+    assert!(is_token(&name), "invalid header name {name:?}");
+    assert!(
+        !value.chars().any(|c| c.is_ascii_control() && c != '\t'),
+        "control character in value of header {name:?}"
+    );
+    (name, value)
+}
+
+/// A parsed HTTP request.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub(crate) method: Method,
+    pub(crate) target: String,
+    pub(crate) path: String,
+    pub(crate) query: Option<String>,
+    pub(crate) version: Version,
+    pub(crate) headers: Headers,
+    pub(crate) body: Vec<u8>,
+    pub(crate) trailers: Headers,
+    pub(crate) params: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The request target exactly as sent, e.g. `/search?q=rust`.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Path part of the target, without the query. Still percent-encoded.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Query part of the target, without the `?`, if there is one.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Whether the client wants the connection kept open after this
+    /// request: HTTP/1.1 connections persist unless the client sends
+    /// `Connection: close`, HTTP/1.0 ones only if it sends
+    /// `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http11 => !self.headers.has_token("Connection", "close"),
+            Version::Http10 => self.headers.has_token("Connection", "keep-alive"),
+        }
+    }
+
+    /// The body, with any chunked transfer coding already removed.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Path parameter `name` captured by the [`Router`](super::Router)
+    /// route that matched the request, e.g. `id` for `/users/:id`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Fields sent after a chunked body. Empty for other requests.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lookup_ignores_case() {
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/html");
+        headers.append("Connection", "keep-alive, Upgrade");
+        headers.append("connection", "TE");
+
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(
+            headers.get_all("CONNECTION").collect::<Vec<_>>(),
+            ["keep-alive, Upgrade", "TE"]
+        );
+        assert!(headers.has_token("Connection", "upgrade"));
+        assert!(headers.has_token("Connection", "te"));
+        assert!(!headers.has_token("Connection", "close"));
+
+        headers.set("CONNECTION", "close");
+        assert_eq!(headers.get_all("connection").collect::<Vec<_>>(), ["close"]);
+        assert_eq!(headers.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "control character in value of header \"Location\"")]
+    fn header_values_cannot_start_a_new_line() {
+        Headers::new().set("Location", "/home\r\nSet-Cookie: admin=1");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid header name")]
+    fn header_names_must_be_tokens() {
+        Headers::new().append("X-Injected\r\nSet-Cookie", "admin=1");
+    }
+}
@@ -0,0 +1,94 @@
+use super::Headers;
+
+/// An HTTP response, as returned by a [`Handler`](super::Handler).
+///
+/// The server adds `Content-Length` and `Connection` itself, so handlers
+/// only set the headers that describe the content. Responses with a
+/// status of 1xx, 204 or 304 are sent without a body.
+///
+/// ```
+/// use rust_server::Response;
+///
+/// let response = Response::builder()
+///     .status(201)
+///     .header("Content-Type", "text/plain")
+///     .body("created")
+///     .build();
+/// assert_eq!(response.status(), 201);
+/// assert_eq!(response.body(), b"created");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// A response with `status`, no headers and an empty body.
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Start building a `200 OK` response.
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            response: Response::new(200),
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// Builds a [`Response`]; see [`Response::builder`].
+#[derive(Debug, Clone)]
+pub struct ResponseBuilder {
+    response: Response,
+}
+
+impl ResponseBuilder {
+    /// Status code of the response. Defaults to 200.
+    pub fn status(mut self, status: u16) -> ResponseBuilder {
+        self.response.status = status;
+        self
+    }
+
+    /// Add a header, keeping any earlier ones of the same name.
+    ///
+    /// # Panics
+    ///
+    /// Panics on an invalid name or a value with CR, LF or another control
+    /// character; see [`Headers::append`].
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> ResponseBuilder {
+        self.response.headers.append(name, value);
+        self
+    }
+
+    /// Body of the response. Defaults to empty.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> ResponseBuilder {
+        self.response.body = body.into();
+        self
+    }
+
+    pub fn build(self) -> Response {
+        self.response
+    }
+}
@@ -0,0 +1,347 @@
+use super::{Handler, Method, Request, Response};
+
+/// A [`Handler`] that passes each request on to the handler registered
+/// for its method and path.
+///
+/// Patterns are matched segment by segment against the request path:
+///
+/// - a literal segment such as `users` matches only itself;
+/// - `:name` matches any one non-empty segment and captures it as the
+///   parameter `name`;
+/// - `*name`, only allowed last, matches the rest of the path, including
+///   nothing at all, and captures it as `name`. A bare `*` captures
+///   nothing.
+///
+/// Routes are tried in the order they were added, and the first match
+/// wins. Captured parameters are percent-decoded and can be read with
+/// [`Request::param`]. `GET` routes also answer `HEAD` requests.
+///
+/// A path that no route matches gets a 404. A path that only routes for
+/// other methods match gets a 405 listing them in `Allow`.
+///
+/// ```
+/// use rust_server::http::{Method, Router};
+/// use rust_server::{Request, Response};
+///
+/// let router = Router::new()
+///     .get("/users/:id", |request: Request| {
+///         let id = request.param("id").unwrap();
+///         Response::builder().body(format!("user {id}")).build()
+///     })
+///     .route(Method::Delete, "/users/:id", |_: Request| Response::new(204))
+///     .get("/static/*file", |_: Request| Response::new(404));
+/// # let _ = router;
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+struct Route {
+    method: Method,
+    pattern: Vec<Segment>,
+    handler: Box<dyn Handler>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(Option<String>),
+}
+
+/// Outcome of [`Router::resolve`].
+pub enum Routed<'a> {
+    /// A route matched both method and path.
+    Found(&'a dyn Handler),
+    /// Routes match the path, but none of them the method. Holds the
+    /// methods they accept, for the `Allow` header of a 405. `HEAD` is
+    /// only listed if a route was added for it explicitly.
+    MethodNotAllowed(Vec<Method>),
+    /// No route matches the path; answer with a 404.
+    NotFound,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Add a route for `method` requests whose path matches `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` doesn't start with `/`, has an unnamed `:`
+    /// parameter or has a wildcard anywhere but at the end.
+    pub fn route(mut self, method: Method, pattern: &str, handler: impl Handler) -> Router {
+        self.routes.push(Route {
+            method,
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Add a route for `GET` requests.
+    pub fn get(self, pattern: &str, handler: impl Handler) -> Router {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    /// Add a route for `POST` requests.
+    pub fn post(self, pattern: &str, handler: impl Handler) -> Router {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Add a route for `PUT` requests.
+    pub fn put(self, pattern: &str, handler: impl Handler) -> Router {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    /// Add a route for `DELETE` requests.
+    pub fn delete(self, pattern: &str, handler: impl Handler) -> Router {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// Find the handler for `request` and store the parameters its
+    /// pattern captured in the request.
+    pub fn resolve(&self, request: &mut Request) -> Routed<'_> {
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, &request.path) else {
+                continue;
+            };
+            if route.method == request.method
+                || (route.method == Method::Get && request.method == Method::Head)
+            {
+                request.params = params;
+                return Routed::Found(route.handler.as_ref());
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+        if allowed.is_empty() {
+            Routed::NotFound
+        } else {
+            Routed::MethodNotAllowed(allowed)
+        }
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, mut request: Request) -> Response {
+        match self.resolve(&mut request) {
+            Routed::Found(handler) => handler.handle(request),
+            Routed::MethodNotAllowed(mut allowed) => {
+                // `GET` routes answer `HEAD` too.
+                if let Some(get) = allowed.iter().position(|method| *method == Method::Get) {
+                    if !allowed.contains(&Method::Head) {
+                        allowed.insert(get + 1, Method::Head);
+                    }
+                }
+                let allowed: Vec<_> = allowed.iter().map(Method::as_str).collect();
+                Response::builder()
+                    .status(405)
+                    .header("Allow", allowed.join(", "))
+                    .build()
+            }
+            Routed::NotFound => Response::new(404),
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let Some(rest) = pattern.strip_prefix('/') else {
+        panic!("route pattern {pattern:?} must start with `/`");
+    };
+    let segments: Vec<_> = rest
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                assert!(!name.is_empty(), "unnamed parameter in {pattern:?}");
+                Segment::Param(name.to_string())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Rest((!name.is_empty()).then(|| name.to_string()))
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect();
+    let last = segments.len() - 1;
+    assert!(
+        !segments[..last]
+            .iter()
+            .any(|segment| matches!(segment, Segment::Rest(_))),
+        "wildcard before the end of {pattern:?}"
+    );
+    segments
+}
+
+/// Match `path` against `pattern`, returning the captured parameters.
+fn match_path(pattern: &[Segment], path: &str) -> Option<Vec<(String, String)>> {
+    let mut rest = path.strip_prefix('/')?;
+    let mut params = Vec::new();
+    for (i, segment) in pattern.iter().enumerate() {
+        if let Segment::Rest(name) = segment {
+            if let Some(name) = name {
+                params.push((name.clone(), percent_decode(rest)));
+            }
+            return Some(params);
+        }
+        let (current, next) = match rest.split_once('/') {
+            Some((current, next)) => (current, Some(next)),
+            None => (rest, None),
+        };
+        match segment {
+            Segment::Literal(literal) if literal == current => {}
+            Segment::Param(name) if !current.is_empty() => {
+                params.push((name.clone(), percent_decode(current)));
+            }
+            _ => return None,
+        }
+        match next {
+            Some(next) => rest = next,
+            // The path is used up; only a trailing wildcard may remain.
+            None => {
+                return match pattern.get(i + 1) {
+                    None => Some(params),
+                    Some(Segment::Rest(name)) => {
+                        if let Some(name) = name {
+                            params.push((name.clone(), String::new()));
+                        }
+                        Some(params)
+                    }
+                    Some(_) => None,
+                };
+            }
+        }
+    }
+    // The pattern is used up, but the path goes on.
+    None
+}
+
+/// Decode `%XX` escapes. Input that doesn't decode to UTF-8 is returned as
+/// it was.
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| input.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::read_request;
+
+    fn request(method: &str, target: &str) -> Request {
+        let input = format!("{method} {target} HTTP/1.0\r\n\r\n");
+        read_request(&mut input.as_bytes(), 0).unwrap().unwrap()
+    }
+
+    /// Answers with its name followed by the parameters it was given.
+    fn named(name: &'static str, params: &'static [&'static str]) -> impl Handler {
+        move |request: Request| {
+            let mut body = name.to_string();
+            for param in params {
+                body += &format!(" {param}={}", request.param(param).unwrap());
+            }
+            Response::builder().body(body).build()
+        }
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", named("index", &[]))
+            .get("/users/:id", named("show", &["id"]))
+            .route(Method::Patch, "/users/:id", named("update", &["id"]))
+            .delete("/users/:id", named("delete", &["id"]))
+            .get("/users/:id/posts/:post", named("post", &["id", "post"]))
+            .get("/static/*file", named("static", &["file"]))
+            .get("/any/*", named("any", &[]))
+    }
+
+    fn body_of(router: &Router, method: &str, target: &str) -> String {
+        let response = router.handle(request(method, target));
+        assert_eq!(response.status(), 200, "{method} {target}");
+        String::from_utf8(response.body().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn matches_literals_and_parameters() {
+        let router = router();
+        assert_eq!(body_of(&router, "GET", "/"), "index");
+        assert_eq!(body_of(&router, "HEAD", "/"), "index");
+        assert_eq!(
+            body_of(&router, "PATCH", "/users/42?full=1"),
+            "update id=42"
+        );
+        assert_eq!(
+            body_of(&router, "GET", "/users/j%C3%B6rg/posts/7"),
+            "post id=jörg post=7"
+        );
+
+        let mut request = request("GET", "/users/7");
+        assert!(matches!(router.resolve(&mut request), Routed::Found(_)));
+        assert_eq!(request.param("missing"), None);
+    }
+
+    #[test]
+    fn wildcards_capture_the_rest_of_the_path() {
+        let router = router();
+        assert_eq!(
+            body_of(&router, "GET", "/static/css/site%20main.css"),
+            "static file=css/site main.css"
+        );
+        assert_eq!(body_of(&router, "GET", "/static"), "static file=");
+        assert_eq!(body_of(&router, "GET", "/any/thing/at/all"), "any");
+    }
+
+    #[test]
+    fn distinguishes_wrong_method_from_unknown_path() {
+        let router = router();
+        let response = router.handle(request("POST", "/users/1"));
+        assert_eq!(response.status(), 405);
+        assert_eq!(
+            response.headers().get("Allow"),
+            Some("GET, HEAD, PATCH, DELETE")
+        );
+        let response = router.handle(request("PUT", "/any/thing"));
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD"));
+        let response = Router::new()
+            .delete("/users/:id", named("delete", &["id"]))
+            .handle(request("GET", "/users/1"));
+        assert_eq!(response.headers().get("Allow"), Some("DELETE"));
+        for path in [
+            "/users",
+            "/users/",
+            "/users/1/posts",
+            "/nope",
+            "/users/1/extra",
+        ] {
+            assert_eq!(router.handle(request("GET", path)).status(), 404, "{path}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard before the end")]
+    fn wildcard_must_come_last() {
+        let _ = Router::new().get("/*rest/edit", named("edit", &[]));
+    }
+}
@@ -0,0 +1,752 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, warn};
+
+use super::{read_request, reason_phrase, Handler, Method, Response};
+use crate::{panic_message, Shared, ThreadPool};
+
+/// How long a closing connection keeps reading what the client still
+/// sends, and how much of it.
+const LINGER_TIME: Duration = Duration::from_secs(2);
+const LINGER_BYTES: u64 = 1024 * 1024;
+
+/// Lingering bound for connections handed to the [`Reaper`], whose
+/// clients have not sent anything we care about.
+const REAPER_LINGER_TIME: Duration = Duration::from_millis(100);
+
+/// How often a kept-alive connection waiting for its next request checks
+/// whether other connections need its worker.
+const IDLE_POLL: Duration = Duration::from_millis(20);
+
+/// Per-connection limits, copied into every connection job.
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_body: usize,
+    idle_timeout: Duration,
+    max_requests: usize,
+}
+
+/// Configures and creates a [`Server`].
+///
+/// ```no_run
+/// use rust_server::{Request, Response, Server, ThreadPool};
+/// use std::time::Duration;
+///
+/// let server = Server::builder()
+///     .pool(ThreadPool::new(4))
+///     .max_body(64 * 1024)
+///     .idle_timeout(Duration::from_secs(10))
+///     .bind("127.0.0.1:7878")
+///     .unwrap();
+/// server.serve(|_: Request| Response::builder().body("hello").build());
+/// ```
+pub struct ServerBuilder {
+    pool: Option<ThreadPool>,
+    limits: Limits,
+    shed_load: Option<(usize, Duration)>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        ServerBuilder::new()
+    }
+}
+
+impl ServerBuilder {
+    /// Start from a 1 MiB body limit, a 5 second idle timeout, 100
+    /// requests per connection and no load shedding.
+    pub fn new() -> ServerBuilder {
+        ServerBuilder {
+            pool: None,
+            limits: Limits {
+                max_body: 1024 * 1024,
+                idle_timeout: Duration::from_secs(5),
+                max_requests: 100,
+            },
+            shed_load: None,
+        }
+    }
+
+    /// Serve connections on `pool`. Each connection occupies a worker for
+    /// as long as it stays open, so the pool bounds how many clients are
+    /// served at once.
+    ///
+    /// Defaults to a pool with one thread per CPU, named `http-<id>`.
+    pub fn pool(mut self, pool: ThreadPool) -> ServerBuilder {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Largest request body accepted, in bytes. Requests with longer
+    /// bodies get a 413.
+    pub fn max_body(mut self, bytes: usize) -> ServerBuilder {
+        self.limits.max_body = bytes;
+        self
+    }
+
+    /// How long a kept-alive connection may wait for its next request
+    /// before it is closed. Also bounds stalls in the middle of a request.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout` is zero.
+    pub fn idle_timeout(mut self, timeout: Duration) -> ServerBuilder {
+        assert!(!timeout.is_zero(), "idle timeout must be non-zero");
+        self.limits.idle_timeout = timeout;
+        self
+    }
+
+    /// Requests served on one connection before it is closed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero.
+    pub fn max_requests(mut self, count: usize) -> ServerBuilder {
+        assert!(count > 0, "connections must serve at least one request");
+        self.limits.max_requests = count;
+        self
+    }
+
+    /// Answer new connections with a 503 and a `Retry-After` of
+    /// `retry_after` while the pool is saturated, i.e. every worker is
+    /// busy, the pool can't grow and `max_queued` connections are already
+    /// waiting. See [`ThreadPool::is_saturated`].
+    ///
+    /// A connection that finds the queue of a bounded pool full gets a 503
+    /// either way, with a `Retry-After` only if this is set.
+    pub fn shed_load(mut self, max_queued: usize, retry_after: Duration) -> ServerBuilder {
+        self.shed_load = Some((max_queued, retry_after));
+        self
+    }
+
+    /// Listen on `addr`.
+    pub fn bind(self, addr: impl ToSocketAddrs) -> io::Result<Server> {
+        let pool = match self.pool {
+            Some(pool) => pool,
+            None => ThreadPool::builder()
+                .thread_name("http")
+                .build()
+                .map_err(io::Error::other)?,
+        };
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            pool,
+            limits: self.limits,
+            shed_load: self.shed_load,
+        })
+    }
+}
+
+/// An HTTP/1.1 server that hands every request to a [`Handler`].
+///
+/// Connections are served on a [`ThreadPool`], one job per connection.
+/// They are kept alive between requests as HTTP/1.0 and 1.1 prescribe, and
+/// pipelined requests are answered in order.
+pub struct Server {
+    listener: TcpListener,
+    pool: ThreadPool,
+    limits: Limits,
+    shed_load: Option<(usize, Duration)>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Listen on `addr` with the default settings of [`ServerBuilder`].
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Server> {
+        ServerBuilder::new().bind(addr)
+    }
+
+    /// The address the server listens on, e.g. to find the port picked
+    /// for `127.0.0.1:0`.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The pool connections are served on.
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    /// Accept connections and answer their requests with `handler`. Never
+    /// returns; failed accepts are logged and skipped.
+    pub fn serve(self, handler: impl Handler) {
+        let handler = Arc::new(handler);
+        let reaper = Reaper::start();
+        let shared = Arc::downgrade(&self.pool.shared);
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            let retry_after = self.shed_load.map(|(_, retry_after)| retry_after);
+            if let Some((max_queued, retry_after)) = self.shed_load {
+                if self.pool.is_saturated(max_queued) {
+                    reject_connection(stream, Some(retry_after), &reaper);
+                    continue;
+                }
+            }
+            // Shared with the job, so the stream is still at hand to answer
+            // on if the job doesn't fit in the queue.
+            let stream = Arc::new(stream);
+            let job_stream = Arc::clone(&stream);
+            let handler = Arc::clone(&handler);
+            let limits = self.limits;
+            let (shared, job_reaper) = (shared.clone(), reaper.clone());
+            let queued = self.pool.try_execute(move || {
+                serve_connection(&job_stream, handler.as_ref(), limits, &shared, &job_reaper);
+            });
+            if let Err(job) = queued {
+                warn!("job queue is full; rejecting connection");
+                drop(job);
+                if let Ok(stream) = Arc::try_unwrap(stream) {
+                    reject_connection(stream, retry_after, &reaper);
+                }
+            }
+        }
+    }
+}
+
+/// Answer straight from the accept thread without reading the request, so a
+/// saturated pool fails fast instead of leaving the client hanging.
+fn reject_connection(mut stream: TcpStream, retry_after: Option<Duration>, reaper: &Reaper) {
+    let mut response = Response::new(503);
+    if let Some(retry_after) = retry_after {
+        let seconds = retry_after.as_secs().to_string();
+        response.headers_mut().append("Retry-After", seconds);
+    }
+    // A slow client must not stall the accept loop.
+    let _ = stream.set_write_timeout(Some(Duration::from_millis(500)));
+    match write_response(&mut stream, &response, false, None) {
+        Ok(()) => reaper.linger(stream),
+        Err(err) => debug!("failed to send 503: {err}"),
+    }
+}
+
+/// Lingering close, as in [`linger_close`], for the connections the accept
+/// thread rejects and the idle ones whose worker is needed elsewhere. A
+/// single thread drains them all without blocking, so clients that keep
+/// their end open can't hold up the accept loop or a worker.
+#[derive(Clone)]
+struct Reaper {
+    streams: mpsc::Sender<TcpStream>,
+}
+
+/// A rejected connection being drained by the [`Reaper`].
+struct Lingering {
+    stream: TcpStream,
+    until: Instant,
+    left: u64,
+}
+
+impl Reaper {
+    /// How often lingering connections are drained.
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    fn start() -> Reaper {
+        let (streams, received) = mpsc::channel();
+        thread::Builder::new()
+            .name("http-reaper".to_string())
+            .spawn(move || Reaper::run(received))
+            .unwrap_or_else(|err| panic!("failed to spawn the reaper thread: {err}"));
+        Reaper { streams }
+    }
+
+    /// Stop sending on `stream` and have the reaper drain and close it.
+    fn linger(&self, stream: TcpStream) {
+        if stream.shutdown(Shutdown::Write).is_ok() && stream.set_nonblocking(true).is_ok() {
+            let _ = self.streams.send(stream);
+        }
+    }
+
+    fn run(streams: mpsc::Receiver<TcpStream>) {
+        let mut lingering: Vec<Lingering> = Vec::new();
+        let mut discard = [0; 4096];
+        loop {
+            let received = if lingering.is_empty() {
+                streams.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                streams.recv_timeout(Reaper::INTERVAL)
+            };
+            match received {
+                Ok(stream) => lingering.push(Lingering {
+                    stream,
+                    until: Instant::now() + REAPER_LINGER_TIME,
+                    left: LINGER_BYTES,
+                }),
+                Err(RecvTimeoutError::Disconnected) if lingering.is_empty() => return,
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(Reaper::INTERVAL),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            lingering.retain_mut(|lingering| lingering.drain(&mut discard));
+        }
+    }
+}
+
+impl Lingering {
+    /// Discard what has arrived so far. Returns whether to keep lingering.
+    fn drain(&mut self, discard: &mut [u8]) -> bool {
+        while self.left > 0 {
+            match self.stream.read(discard) {
+                Ok(0) => return false,
+                Ok(read) => self.left = self.left.saturating_sub(read as u64),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Instant::now() < self.until;
+                }
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+}
+
+/// Serve requests off `stream` until the client closes it, asks for it to be
+/// closed, sits idle too long or reaches the request limit. Pipelined
+/// requests wait in the reader and are answered in order.
+///
+/// A connection that sits idle between requests while other connections
+/// wait in the queue of `shared` is closed, so its worker can serve them.
+fn serve_connection(
+    stream: &TcpStream,
+    handler: &dyn Handler,
+    limits: Limits,
+    shared: &Weak<Shared>,
+    reaper: &Reaper,
+) {
+    let mut reader = BufReader::new(stream);
+    let mut writer = stream;
+    let others_waiting = || {
+        shared
+            .upgrade()
+            .is_some_and(|shared| shared.queue.len() > 0)
+    };
+    for served in 1..=limits.max_requests {
+        if served > 1 && !await_request(stream, &mut reader, limits.idle_timeout, others_waiting) {
+            debug!("closing idle connection");
+            if let Ok(stream) = stream.try_clone() {
+                reaper.linger(stream);
+            }
+            return;
+        }
+        // Bounds both the wait for the next request and stalls inside one.
+        if stream.set_read_timeout(Some(limits.idle_timeout)).is_err() {
+            return;
+        }
+        let request = match read_request(&mut reader, limits.max_body) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(err) => {
+                debug!("rejecting request: {err}");
+                if let Some(status) = err.status() {
+                    // There is no telling where the next request would
+                    // start, so the connection is closed after this.
+                    let reason = reason_phrase(status);
+                    let response = Response::builder()
+                        .status(status)
+                        .header("Content-Type", "text/plain")
+                        .body(reason)
+                        .build();
+                    if write_response(&mut writer, &response, false, None).is_ok() {
+                        linger_close(stream, &mut reader, LINGER_TIME);
+                    }
+                }
+                return;
+            }
+        };
+        let head = *request.method() == Method::Head;
+        let mut keep_alive = request.keep_alive() && served < limits.max_requests;
+
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| handler.handle(request))) {
+            Ok(response) => response,
+            Err(payload) => {
+                error!("handler panicked: {}", panic_message(payload.as_ref()));
+                keep_alive = false;
+                Response::new(500)
+            }
+        };
+        keep_alive &= !response.headers().has_token("Connection", "close");
+
+        let persist = keep_alive.then(|| (limits.idle_timeout, limits.max_requests - served));
+        if let Err(err) = write_response(&mut writer, &response, head, persist) {
+            debug!("failed to send response: {err}");
+            return;
+        }
+        if !keep_alive {
+            // Requests the client already pipelined stay unanswered.
+            linger_close(stream, &mut reader, LINGER_TIME);
+            return;
+        }
+    }
+}
+
+/// Wait for the next request on a kept-alive connection. Returns `false` if
+/// it should be closed instead: the client stayed quiet for `idle_timeout`,
+/// or `others_waiting` found other connections waiting for a worker.
+fn await_request(
+    stream: &TcpStream,
+    reader: &mut BufReader<&TcpStream>,
+    idle_timeout: Duration,
+    others_waiting: impl Fn() -> bool,
+) -> bool {
+    if !reader.buffer().is_empty() {
+        return true;
+    }
+    let deadline = Instant::now() + idle_timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || others_waiting() {
+            return false;
+        }
+        if stream.set_read_timeout(Some(left.min(IDLE_POLL))).is_err() {
+            return false;
+        }
+        match reader.fill_buf() {
+            // Data or the end of the stream, which `read_request` handles.
+            Ok(_) => return true,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Close the connection after the last response without losing it.
+///
+/// Closing a socket with unread input makes the kernel reset the
+/// connection, and a reset can destroy the response before the client
+/// reads it. So stop sending, then read and discard whatever `reader` still
+/// delivers, for at most `time` and `LINGER_BYTES`, before dropping it.
+fn linger_close(stream: &TcpStream, reader: impl Read, time: Duration) {
+    if stream.shutdown(Shutdown::Write).is_err() {
+        return;
+    }
+    let deadline = Instant::now() + time;
+    let mut reader = reader.take(LINGER_BYTES);
+    let mut discard = [0; 4096];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || stream.set_read_timeout(Some(left)).is_err() {
+            return;
+        }
+        match reader.read(&mut discard) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Write `response`, framed by `Content-Length`. `persist` holds the idle
+/// timeout and remaining requests if the connection stays open, and
+/// `None` if it is closed afterwards. Responses to `HEAD` leave out the
+/// body but keep its length; 1xx, 204 and 304 responses have neither.
+fn write_response(
+    writer: &mut impl Write,
+    response: &Response,
+    head: bool,
+    persist: Option<(Duration, usize)>,
+) -> io::Result<()> {
+    let status = response.status();
+    let mut out = format!("HTTP/1.1 {status} {}\r\n", reason_phrase(status));
+    for (name, value) in response.headers().iter() {
+        let framing = [
+            "Content-Length",
+            "Transfer-Encoding",
+            "Connection",
+            "Keep-Alive",
+        ];
+        if !framing.iter().any(|field| field.eq_ignore_ascii_case(name)) {
+            out += &format!("{name}: {value}\r\n");
+        }
+    }
+    let bodiless = status < 200 || status == 204 || status == 304;
+    if !bodiless {
+        out += &format!("Content-Length: {}\r\n", response.body().len());
+    }
+    match persist {
+        Some((timeout, remaining)) => {
+            out += &format!(
+                "Connection: keep-alive\r\nKeep-Alive: timeout={}, max={remaining}\r\n",
+                timeout.as_secs()
+            );
+        }
+        None => out += "Connection: close\r\n",
+    }
+    out += "\r\n";
+
+    let mut out = out.into_bytes();
+    if !head && !bodiless {
+        out.extend_from_slice(response.body());
+    }
+    writer.write_all(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Request, Router};
+    use crate::tests::block_workers;
+    use std::io::Read;
+    use std::thread;
+
+    /// Start a server on a free port and return its address. The server
+    /// thread lives until the test binary exits.
+    fn start(builder: ServerBuilder, handler: impl Handler) -> SocketAddr {
+        let server = builder
+            .pool(ThreadPool::new(2))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve(handler));
+        addr
+    }
+
+    /// Send `input` and read until the server closes the connection.
+    fn exchange(addr: SocketAddr, input: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream.write_all(input.as_bytes()).unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .get("/hello/:name", |request: Request| {
+                let name = request.param("name").unwrap();
+                Response::builder()
+                    .header("Content-Type", "text/plain")
+                    .body(format!("hello {name}"))
+                    .build()
+            })
+            .post("/echo", |request: Request| {
+                Response::builder().body(request.body()).build()
+            })
+            .get("/panic", |_: Request| -> Response {
+                panic!("handler boom")
+            })
+    }
+
+    #[test]
+    fn answers_pipelined_requests_in_order() {
+        let addr = start(Server::builder().max_requests(3), app());
+        let output = exchange(
+            addr,
+            "GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n\
+             POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nping\
+             HEAD /hello/bob HTTP/1.1\r\nHost: x\r\n\r\n\
+             GET /hello/never HTTP/1.1\r\nHost: x\r\n\r\n",
+        );
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 9\r\n\
+             Connection: keep-alive\r\nKeep-Alive: timeout=5, max=2\r\n\r\nhello ann\
+             HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\
+             Connection: keep-alive\r\nKeep-Alive: timeout=5, max=1\r\n\r\nping\
+             HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 9\r\n\
+             Connection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn closes_http10_connections_and_reports_errors() {
+        let addr = start(Server::builder().max_body(2), app());
+        let output = exchange(addr, "DELETE /echo HTTP/1.0\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 405 Method Not Allowed\r\nAllow: POST\r\n"));
+        assert!(output.contains("Connection: close\r\n"));
+
+        let output = exchange(
+            addr,
+            "POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc",
+        );
+        assert!(output.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        let output = exchange(addr, "GET /panic HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(output.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn rejected_bodies_are_drained_so_the_error_arrives() {
+        let addr = start(Server::builder().max_body(16), app());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        // More than the socket buffers hold, so most of it is still
+        // unsent when the server answers.
+        let sender = thread::spawn(move || {
+            let body = vec![b'a'; 512 * 1024];
+            let head = format!(
+                "POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            writer.write_all(head.as_bytes())?;
+            writer.write_all(&body)
+        });
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+        assert!(sender.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn request_limit_closes_cleanly_despite_pipelined_requests() {
+        let addr = start(Server::builder().max_requests(1), app());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        // Far more than the server reads ahead, so most of it is still
+        // unread when the server closes the connection.
+        let sender = thread::spawn(move || {
+            let requests = "GET /hello/a HTTP/1.1\r\nHost: x\r\n\r\n".repeat(4000);
+            writer.write_all(requests.as_bytes())
+        });
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("Connection: close\r\n"));
+        assert_eq!(output.matches("HTTP/1.1").count(), 1);
+        assert!(sender.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn saturated_server_sheds_load() {
+        let server = Server::builder()
+            .pool(ThreadPool::new(1))
+            .shed_load(0, Duration::from_secs(7))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        // Pin the only worker, so the pool is saturated.
+        let release_tx = block_workers(server.pool(), 1);
+        thread::spawn(move || server.serve(app()));
+
+        let output = exchange(addr, "GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(
+            output,
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 7\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+
+        // Clients that keep their end open must not hold up the others.
+        let start = Instant::now();
+        let clients: Vec<_> = (0..30)
+            .map(|_| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream
+                    .write_all(b"GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n")
+                    .unwrap();
+                stream
+            })
+            .collect();
+        // Borrowed, so every client stays open until all are answered.
+        for mut stream in &clients {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut output = String::new();
+            stream.read_to_string(&mut output).unwrap();
+            assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        }
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < Duration::from_secs(1),
+            "shedding took {elapsed:?}"
+        );
+        drop(release_tx);
+    }
+
+    #[test]
+    fn full_queue_is_answered_with_503() {
+        let server = Server::builder()
+            .pool(ThreadPool::bounded(1, 1))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        // Pin the only worker and fill the only queue slot.
+        let release_tx = block_workers(server.pool(), 1);
+        server.pool().execute(|| {});
+        thread::spawn(move || server.serve(app()));
+
+        let output = exchange(addr, "GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(
+            output,
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\
+             Connection: close\r\n\r\n"
+        );
+        drop(release_tx);
+    }
+
+    #[test]
+    fn bodiless_statuses_get_no_length_or_body() {
+        for status in [101, 204, 304] {
+            let response = Response::builder().status(status).body("stray").build();
+            let mut out = Vec::new();
+            write_response(&mut out, &response, false, None).unwrap();
+            let reason = reason_phrase(status);
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                format!("HTTP/1.1 {status} {reason}\r\nConnection: close\r\n\r\n")
+            );
+        }
+    }
+
+    #[test]
+    fn idle_connections_time_out() {
+        let addr = start(
+            Server::builder().idle_timeout(Duration::from_millis(100)),
+            app(),
+        );
+        let output = exchange(addr, "GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(output.ends_with("\r\n\r\nhello ann"));
+    }
+
+    #[test]
+    fn idle_connections_give_way_to_waiting_ones() {
+        let server = Server::builder()
+            .pool(ThreadPool::new(1))
+            .idle_timeout(Duration::from_secs(5))
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.serve(app()));
+
+        // The first client keeps its connection open after one request,
+        // holding the only worker while it waits for the next.
+        let mut idle = TcpStream::connect(addr).unwrap();
+        idle.set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        idle.write_all(b"GET /hello/ann HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        let mut response = [0; 1024];
+        let read = idle.read(&mut response).unwrap();
+        assert!(response[..read].ends_with(b"\r\n\r\nhello ann"));
+
+        let started = Instant::now();
+        let output = exchange(addr, "GET /hello/bob HTTP/1.0\r\n\r\n");
+        assert!(output.ends_with("\r\n\r\nhello bob"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // The idle connection was closed to make room.
+        assert_eq!(idle.read(&mut response).unwrap(), 0);
+    }
+}
@@ -1,11 +1,190 @@
-use std::sync::{Arc, Mutex};
-use std::{sync::mpsc, thread};
+use std::any::Any;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+mod affinity;
+mod builder;
+mod cancel;
+mod context;
+mod group;
+mod handle;
+pub mod http;
+mod parallel;
+mod queue;
+mod scope;
+mod shutdown;
+mod stats;
+mod task;
+mod timer;
+mod worker;
+
+pub use affinity::CpuAffinity;
+pub use builder::{BuildError, ThreadPoolBuilder};
+pub use cancel::CancellationToken;
+pub use context::WorkerContext;
+pub use group::{JobError, JobGroup};
+pub use handle::JobHandle;
+pub use http::{Handler, Request, Response, Server};
+pub use queue::Priority;
+pub use scope::Scope;
+pub use shutdown::{ShutdownPolicy, ShutdownReport};
+pub use stats::{Histogram, PoolStats};
+pub use task::block_on;
+pub use timer::ScheduledHandle;
+
+use queue::JobQueue;
+use stats::Metrics;
+use timer::Timer;
+use worker::{Startup, ThreadConfig, Worker};
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// Called with the worker id and panic payload whenever a job panics.
+type PanicHandler = dyn Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static;
+
+/// A pool of worker threads that run submitted jobs.
+///
+/// Worker events (jobs finishing, workers retiring, dying or being respawned)
+/// are reported through the [`log`] crate with the worker id and job duration
+/// as key-value fields. Nothing is printed unless the application installs a
+/// logger.
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    shared: Arc<Shared>,
+}
+
+/// State shared between the pool handle and its workers.
+struct Shared {
+    queue: JobQueue,
+    active: AtomicUsize,
+    workers: Mutex<Vec<Worker>>,
+    panic_handler: Mutex<Arc<PanicHandler>>,
+    thread_config: ThreadConfig,
+    /// Workers that are always kept alive.
+    core: AtomicUsize,
+    /// Upper bound the pool may grow to while jobs back up.
+    max: AtomicUsize,
+    /// Workers that have not retired. Only changed with `workers` locked.
+    live: AtomicUsize,
+    next_id: AtomicUsize,
+    /// Worker threads that have not exited yet, retired ones included.
+    running: Mutex<usize>,
+    stopped: Condvar,
+    shutdown_policy: ShutdownPolicy,
+    /// Set once `shutdown` has run, so dropping the pool doesn't wait again.
+    shut_down: AtomicBool,
+    metrics: Metrics,
+    timer: Timer,
+    /// How far workers got with their start hooks, for `build` to wait on.
+    startup: Mutex<Startup>,
+    started: Condvar,
+}
+
+impl Shared {
+    fn report_panic(&self, id: usize, payload: &(dyn Any + Send)) {
+        let handler = Arc::clone(&self.panic_handler.lock().unwrap());
+        handler(id, payload);
+    }
+
+    /// Start one more worker, unless something else already changed the
+    /// picture by the time we hold the lock.
+    fn spawn_worker(self: &Arc<Self>, wanted: impl Fn(usize) -> bool) -> io::Result<bool> {
+        let mut workers = self.workers.lock().unwrap();
+        if self.queue.is_closed() || !wanted(self.live.load(Ordering::SeqCst)) {
+            return Ok(false);
+        }
+        workers.retain(|worker| {
+            !worker.retired || worker.thread.as_ref().is_some_and(|t| !t.is_finished())
+        });
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        workers.push(Worker::new(id, Arc::clone(self))?);
+        self.live.fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    /// Add a worker if more jobs are waiting than there are idle workers to
+    /// pick them up and there is still room below the maximum.
+    fn grow_if_backlogged(self: &Arc<Self>) {
+        let backlogged = |live: usize| {
+            let idle = live.saturating_sub(self.active.load(Ordering::SeqCst));
+            live < self.max.load(Ordering::SeqCst) && self.queue.len() > idle
+        };
+        if !backlogged(self.live.load(Ordering::SeqCst)) {
+            return;
+        }
+        if let Err(err) = self.spawn_worker(backlogged) {
+            log::error!(error:% = err; "failed to spawn an extra worker");
+        }
+    }
+
+    fn worker_cpus(&self) -> Vec<(usize, Option<usize>)> {
+        let mut cpus: Vec<_> = self
+            .workers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|worker| !worker.retired)
+            .map(|worker| (worker.id, worker.cpu))
+            .collect();
+        cpus.sort_unstable();
+        cpus
+    }
+
+    /// How long an idle worker may wait for a job before it should consider
+    /// retiring, or `None` while the pool is at or below its core size.
+    fn idle_timeout(&self, idle_since: Instant) -> Option<Duration> {
+        if self.live.load(Ordering::SeqCst) > self.core.load(Ordering::SeqCst) {
+            Some(
+                self.thread_config
+                    .keep_alive
+                    .saturating_sub(idle_since.elapsed()),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Decide whether worker `id` should exit: always while the pool is
+    /// above its maximum, and above the core size once the worker has been
+    /// idle for the keep-alive period.
+    fn try_retire(&self, id: usize, idle_since: Instant) -> bool {
+        let surplus = || {
+            let live = self.live.load(Ordering::SeqCst);
+            live > self.max.load(Ordering::SeqCst)
+                || (live > self.core.load(Ordering::SeqCst)
+                    && idle_since.elapsed() >= self.thread_config.keep_alive)
+        };
+        // Cheap check first; this runs after every job.
+        if !surplus() {
+            return false;
+        }
+        let mut workers = self.workers.lock().unwrap();
+        if !surplus() {
+            return false;
+        }
+        if let Some(worker) = workers.iter_mut().find(|worker| worker.id == id) {
+            worker.retired = true;
+        }
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        true
+    }
+}
+
+/// Best-effort text of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+fn default_panic_handler(id: usize, payload: &(dyn Any + Send)) {
+    log::error!(worker = id; "job panicked: {}", panic_message(payload));
 }
 
 impl ThreadPool {
@@ -16,65 +195,373 @@ impl ThreadPool {
     /// # Panics
     ///
     /// The `new` function will panic if the size is zero.
-
     pub fn new(count: usize) -> ThreadPool {
         assert!(count > 0);
-        let mut workers = Vec::with_capacity(count);
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
-        for id in 0..count {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
-        ThreadPool {
-            workers,
-            sender: Some(sender),
-        }
+        ThreadPool::builder()
+            .num_threads(count)
+            .build()
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Create a new ThreadPool whose job queue holds at most `capacity`
+    /// pending jobs.
+    ///
+    /// Once the queue is full, `execute` blocks until a worker picks up a
+    /// job and `try_execute` hands the closure back instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either `count` or `capacity` is zero.
+    pub fn bounded(count: usize, capacity: usize) -> ThreadPool {
+        assert!(count > 0);
+        assert!(capacity > 0);
+        ThreadPool::builder()
+            .num_threads(count)
+            .queue_capacity(capacity)
+            .build()
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Configure a pool with a [`ThreadPoolBuilder`].
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    fn start(
+        core: usize,
+        max: usize,
+        queue: JobQueue,
+        thread_config: ThreadConfig,
+        panic_handler: Option<Arc<PanicHandler>>,
+        shutdown_policy: ShutdownPolicy,
+    ) -> Result<ThreadPool, BuildError> {
+        let shared = Arc::new(Shared {
+            queue,
+            active: AtomicUsize::new(0),
+            workers: Mutex::new(Vec::with_capacity(core)),
+            panic_handler: Mutex::new(
+                panic_handler.unwrap_or_else(|| Arc::new(default_panic_handler)),
+            ),
+            thread_config,
+            core: AtomicUsize::new(core),
+            max: AtomicUsize::new(max),
+            live: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(0),
+            running: Mutex::new(0),
+            stopped: Condvar::new(),
+            shutdown_policy,
+            shut_down: AtomicBool::new(false),
+            metrics: Metrics::new(),
+            timer: Timer::new(),
+            startup: Mutex::new(Startup::default()),
+            started: Condvar::new(),
+        });
+        // If a spawn or a start hook fails, dropping `pool` shuts down the
+        // workers that did start.
+        let pool = ThreadPool { shared };
+        while pool
+            .shared
+            .spawn_worker(|live| live < core)
+            .map_err(BuildError::Spawn)?
+        {}
+        pool.shared.wait_started(core)?;
+        Ok(pool)
     }
 
+    /// Queue `f` to run on one of the workers.
+    ///
+    /// On a bounded pool this blocks while the queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
     pub fn execute<F>(&self, f: F)
         where
         F: FnOnce() + Send + 'static,
         {
-            let job = Box::new(f);
-            self.sender.as_ref().unwrap().send(job).unwrap();
+            self.execute_with_priority(Priority::Normal, f);
         }
-}
 
-pub struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
-}
+    /// Like [`execute`](ThreadPool::execute), but queue `f` at `priority`.
+    ///
+    /// Workers pick more urgent jobs first. Less urgent ones still get a
+    /// regular share of the workers while more urgent work keeps coming, so
+    /// they are delayed but never starved.
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+        if self.shared.queue.push(job, priority).is_err() {
+            panic!("ThreadPool::execute called after shutdown");
+        }
+        self.shared.grow_if_backlogged();
+    }
+
+    /// Queue `f` without blocking.
+    ///
+    /// Returns `Err(f)` if the pool is bounded and its queue is currently
+    /// full, so the caller can decide how to shed the load, or if the pool
+    /// has been shut down.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_execute_with_priority(Priority::Normal, f)
+    }
+
+    /// Like [`try_execute`](ThreadPool::try_execute), but queue `f` at
+    /// `priority`.
+    pub fn try_execute_with_priority<F>(&self, priority: Priority, f: F) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.queue.try_push(f, priority)?;
+        self.shared.grow_if_backlogged();
+        Ok(())
+    }
+
+    /// Queue `f` to run on one of the workers once `delay` has passed.
+    ///
+    /// The returned handle can cancel the job until it starts. Jobs that
+    /// are not due yet when the pool shuts down never run.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> ScheduledHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared
+            .schedule_once(delay, Box::new(f))
+            .expect("ThreadPool::execute_after called after shutdown")
+    }
+
+    /// Run `f` on one of the workers every `interval`, starting one
+    /// interval from now, until the returned handle is cancelled or the
+    /// pool shuts down.
+    ///
+    /// Runs never overlap: the next one is scheduled when the previous one
+    /// finishes, and ticks missed while it ran are skipped. A run that
+    /// panics is reported like any other job and does not stop the
+    /// schedule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero or the pool has been shut down.
+    pub fn execute_every<F>(&self, interval: Duration, f: F) -> ScheduledHandle
+    where
+        F: FnMut() + Send + 'static,
+    {
+        assert!(!interval.is_zero(), "interval must be non-zero");
+        self.shared
+            .schedule_repeating(interval, Box::new(f))
+            .expect("ThreadPool::execute_every called after shutdown")
+    }
 
-impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv();
-            match message {
-                Ok(job) => {
-                    println!("Worker {} got a job; executing.", { id });
-                    job();
-                }
-                Err(_) => {
-                    println!("Worker id {} disconnected. Shutting down", id);
-                    break;
-                }
-            };
+    /// Run `f` on one of the workers and return a handle to its result.
+    ///
+    /// A panic inside `f` is caught and handed to whoever joins the handle
+    /// instead of taking the worker down.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_with_priority(Priority::Normal, f)
+    }
+
+    /// Like [`spawn`](ThreadPool::spawn), but queue `f` at `priority`.
+    pub fn spawn_with_priority<F, T>(&self, priority: Priority, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (completer, handle) = handle::pair();
+        self.execute_with_priority(priority, move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            if result.is_err() {
+                stats::mark_panicked();
+            }
+            completer.complete(result);
         });
-        Worker {
-            id,
-            thread: Some(thread),
+        handle
+    }
+
+    /// Start an empty [`JobGroup`] of jobs on this pool that are awaited
+    /// together.
+    pub fn group<T, E>(&self) -> JobGroup<'_, T, E>
+    where
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        JobGroup::new(self)
+    }
+
+    /// Run `f` with a [`Scope`] whose jobs may borrow from the caller's
+    /// stack, and wait for all of them before returning.
+    ///
+    /// The jobs run on the pool's own workers. If `f` or any of the jobs
+    /// panicked, the first panic is re-raised here once every job is done.
+    ///
+    /// Calling `scope` from a job of the same pool can deadlock if every
+    /// worker ends up waiting for a scope.
+    ///
+    /// ```
+    /// let pool = rust_server::ThreadPool::new(2);
+    /// let mut halves = [vec![1, 2], vec![3, 4]];
+    /// pool.scope(|s| {
+    ///     for half in halves.iter_mut() {
+    ///         s.execute(move || half.push(0));
+    ///     }
+    /// });
+    /// assert_eq!(halves, [vec![1, 2, 0], vec![3, 4, 0]]);
+    /// ```
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        scope::scope(self, f)
+    }
+
+    /// Replace the hook that is told about jobs passed to `execute` that
+    /// panic.
+    ///
+    /// The hook receives the id of the worker and the panic payload. The
+    /// worker itself carries on with the next job. By default the panic
+    /// message is logged as an error. Panics in jobs started with `spawn` are
+    /// handed to their `JobHandle` instead.
+    pub fn set_panic_handler<H>(&self, handler: H)
+    where
+        H: Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static,
+    {
+        *self.shared.panic_handler.lock().unwrap() = Arc::new(handler);
+    }
+
+    /// Change the number of core workers and the maximum the pool may grow
+    /// to.
+    ///
+    /// Missing core workers are started right away. Workers above the new
+    /// maximum exit once they finish their current job, and workers above
+    /// the new core size exit after sitting idle for the keep-alive period.
+    /// Fails with the same errors as [`ThreadPoolBuilder::build`] for an
+    /// invalid combination.
+    pub fn resize(&self, core: usize, max: usize) -> Result<(), BuildError> {
+        builder::check_sizes(core, max)?;
+        self.shared.core.store(core, Ordering::SeqCst);
+        self.shared.max.store(max, Ordering::SeqCst);
+        while self
+            .shared
+            .spawn_worker(|live| live < core)
+            .map_err(BuildError::Spawn)?
+        {}
+        self.shared.queue.wake_all();
+        Ok(())
+    }
+
+    /// Stop accepting jobs and wait up to `timeout` for the workers to
+    /// exit.
+    ///
+    /// Jobs still in the queue are run or dropped according to the pool's
+    /// [`ShutdownPolicy`]. Workers that are still busy when the timeout
+    /// expires are listed in the report and left running in the background.
+    /// Afterwards `execute` and `spawn` panic and `try_execute` hands every
+    /// job back.
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.shared.shut_down.store(true, Ordering::SeqCst);
+        self.shared.shutdown(Some(Instant::now() + timeout))
+    }
+
+    /// Block until the queue is empty and no worker is running a job, so
+    /// the pool can be reused for the next batch of work.
+    ///
+    /// Jobs scheduled with `execute_after` or `execute_every` only count
+    /// once they are due. Calling this from a job of the same pool never
+    /// returns, since that job itself is still running.
+    pub fn wait_idle(&self) {
+        self.shared.queue.wait_idle(None);
+    }
+
+    /// Like [`wait_idle`](ThreadPool::wait_idle), but give up after
+    /// `timeout`.
+    ///
+    /// Returns whether the pool became idle in time.
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+        self.shared.queue.wait_idle(Some(Instant::now() + timeout))
+    }
+
+    /// Number of worker threads in the pool.
+    pub fn size(&self) -> usize {
+        self.shared.live.load(Ordering::SeqCst)
+    }
+
+    /// Number of workers that are kept alive even when idle.
+    pub fn core_threads(&self) -> usize {
+        self.shared.core.load(Ordering::SeqCst)
+    }
+
+    /// Number of workers the pool may grow to while jobs back up.
+    pub fn max_threads(&self) -> usize {
+        self.shared.max.load(Ordering::SeqCst)
+    }
+
+    /// Number of jobs waiting for a free worker.
+    pub fn queued_jobs(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Number of workers currently running a job.
+    pub fn active_workers(&self) -> usize {
+        self.shared.active.load(Ordering::SeqCst)
+    }
+
+    /// Number of workers waiting for a job.
+    pub fn idle_workers(&self) -> usize {
+        self.size().saturating_sub(self.active_workers())
+    }
+
+    /// Snapshot of the pool's current load and of the jobs it has run so
+    /// far.
+    pub fn stats(&self) -> PoolStats {
+        let (completed_jobs, panicked_jobs, queue_wait, execution_time) =
+            self.shared.metrics.snapshot();
+        let workers = self.size();
+        let active_workers = self.active_workers();
+        PoolStats {
+            workers,
+            active_workers,
+            idle_workers: workers.saturating_sub(active_workers),
+            queued_jobs: self.queued_jobs(),
+            worker_cpus: self.shared.worker_cpus(),
+            completed_jobs,
+            panicked_jobs,
+            overrun_jobs: self.shared.metrics.overruns(),
+            queue_wait,
+            execution_time,
         }
     }
+
+    /// Whether every worker is busy, the pool cannot grow any further and
+    /// at least `max_queued` jobs are already waiting.
+    ///
+    /// With a `max_queued` of zero the pool counts as saturated as soon as
+    /// it is at its maximum size and no worker is idle.
+    pub fn is_saturated(&self, max_queued: usize) -> bool {
+        self.idle_workers() == 0
+            && self.size() >= self.max_threads()
+            && self.queued_jobs() >= max_queued
+    }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.take());
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                println!("Shutting down worker {}", worker.id);
-                thread.join().unwrap();
-            }
+        if !self.shared.shut_down.swap(true, Ordering::SeqCst) {
+            self.shared.shutdown(None);
         }
     }
 }
@@ -84,11 +571,173 @@ mod tests{
     #![allow(dead_code,unused)]
     use super::*;
 
+    /// Occupy `count` workers of `pool` with jobs that wait for a message on
+    /// the returned sender, one each, or for it to be dropped. Returns once
+    /// all of them are running.
+    #[cfg(test)]
+    pub(crate) fn block_workers(pool: &ThreadPool, count: usize) -> std::sync::mpsc::Sender<()> {
+        use std::sync::mpsc;
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+        for _ in 0..count {
+            let started_tx = started_tx.clone();
+            let release_rx = Arc::clone(&release_rx);
+            pool.execute(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.lock().unwrap().recv();
+            });
+        }
+        for _ in 0..count {
+            started_rx.recv().unwrap();
+        }
+        release_tx
+    }
+
     #[test]
     fn create_threadpool(){
         let pool = ThreadPool::new(4);
-        assert_eq!(4,pool.workers.len());
-        assert_eq!(pool.sender.is_some(),true);
+        assert_eq!(4,pool.size());
+        assert_eq!(pool.queued_jobs(), 0);
+    }
+
+    #[test]
+    fn try_execute_hands_back_job_when_full() {
+        let pool = ThreadPool::bounded(1, 1);
+        let release_tx = block_workers(&pool, 1);
+
+        assert!(pool.try_execute(|| {}).is_ok());
+        assert_eq!(pool.queued_jobs(), 1);
+        assert!(pool.try_execute(|| {}).is_err());
+
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn saturated_when_all_workers_busy() {
+        let pool = ThreadPool::new(2);
+        assert!(!pool.is_saturated(0));
+
+        let release_tx = block_workers(&pool, 2);
+
+        assert_eq!(pool.idle_workers(), 0);
+        assert!(pool.is_saturated(0));
+        assert!(!pool.is_saturated(1));
+
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn panicking_job_is_reported_and_worker_survives() {
+        use std::sync::mpsc;
+
+        let pool = ThreadPool::new(1);
+        let (panic_tx, panic_rx) = mpsc::channel();
+        let panic_tx = Mutex::new(panic_tx);
+        pool.set_panic_handler(move |id, payload| {
+            let message = panic_message(payload).to_string();
+            panic_tx.lock().unwrap().send((id, message)).unwrap();
+        });
+
+        pool.execute(|| panic!("bad request"));
+        assert_eq!(panic_rx.recv().unwrap(), (0, "bad request".to_string()));
+
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.execute(move || done_tx.send(()).unwrap());
+        done_rx.recv().unwrap();
+    }
+
+    #[test]
+    fn dead_worker_is_respawned() {
+        use std::sync::mpsc;
+
+        let pool = ThreadPool::new(1);
+        // A panicking hook escapes `catch_unwind` and kills the thread.
+        pool.set_panic_handler(|_, _| panic!("hook failed"));
+        pool.execute(|| panic!("bad request"));
+
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.execute(move || done_tx.send(()).unwrap());
+        done_rx.recv().unwrap();
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn grows_under_backlog_and_shrinks_when_idle() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .max_threads(3)
+            .keep_alive(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let release_tx = block_workers(&pool, 3);
+        assert_eq!(pool.size(), 3);
+        assert!(pool.is_saturated(0));
+
+        for _ in 0..3 {
+            release_tx.send(()).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.size() > 1 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn resize_adds_and_retires_workers() {
+        let pool = ThreadPool::new(1);
+        pool.resize(3, 4).unwrap();
+        assert_eq!(pool.size(), 3);
+        assert_eq!(pool.max_threads(), 4);
+
+        pool.resize(1, 1).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.size() > 1 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.size(), 1);
+        assert_eq!(pool.spawn(|| 5).join().unwrap(), 5);
+
+        assert!(matches!(
+            pool.resize(2, 1),
+            Err(BuildError::MaxBelowCore { .. })
+        ));
+    }
+
+    #[test]
+    fn wait_idle_waits_for_every_queued_job() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::mpsc;
+
+        let pool = ThreadPool::new(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        for phase in 1..=2 {
+            for _ in 0..10 {
+                let done = Arc::clone(&done);
+                pool.execute(move || {
+                    std::thread::sleep(Duration::from_millis(2));
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            // The same pool serves the next phase after waiting.
+            pool.wait_idle();
+            assert_eq!(done.load(Ordering::SeqCst), phase * 10);
+            assert_eq!((pool.queued_jobs(), pool.active_workers()), (0, 0));
+        }
+
+        let release_tx = block_workers(&pool, 1);
+        assert!(!pool.wait_idle_timeout(Duration::from_millis(20)));
+        release_tx.send(()).unwrap();
+        assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed: capacity > 0")]
+    fn test_bounded_thread_pool_invalid_capacity() {
+        ThreadPool::bounded(1, 0);
     }
 
     #[test]
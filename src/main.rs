@@ -1,42 +1,135 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use rust_server::http::{Handler, Request, Response, Router, Server};
 use rust_server::ThreadPool;
 use std::{
-    fs,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
+    env, fs,
     thread::{self},
     time::Duration,
 };
 
-fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    let pool = ThreadPool::new(4);
+/// Server settings, read from the environment at startup.
+struct Config {
+    /// Jobs allowed to wait while every worker is busy before new
+    /// connections are answered with a 503 (`SHED_QUEUE_THRESHOLD`).
+    max_queued: usize,
+    /// Seconds sent back in the `Retry-After` header (`SHED_RETRY_AFTER`).
+    retry_after: u64,
+    /// Most verbose log level written to stderr (`LOG_LEVEL`).
+    log_level: LevelFilter,
+    /// Largest request body accepted, in bytes; longer ones get a 413
+    /// (`MAX_BODY_SIZE`).
+    max_body: usize,
+    /// How long a kept-alive connection may sit idle before it is closed
+    /// (`KEEP_ALIVE_TIMEOUT`, in seconds). The connection holds on to its
+    /// worker meanwhile.
+    idle_timeout: Duration,
+    /// Requests served on one connection before it is closed
+    /// (`KEEP_ALIVE_MAX`).
+    max_requests: usize,
+}
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        pool.execute(|| {
-            handle_connection(stream);
-        });
+impl Config {
+    fn from_env() -> Config {
+        Config {
+            max_queued: env_or("SHED_QUEUE_THRESHOLD", 8),
+            retry_after: env_or("SHED_RETRY_AFTER", 5),
+            log_level: env_or("LOG_LEVEL", LevelFilter::Info),
+            max_body: env_or("MAX_BODY_SIZE", 1024 * 1024),
+            idle_timeout: Duration::from_secs(env_or("KEEP_ALIVE_TIMEOUT", 5).max(1)),
+            max_requests: env_or("KEEP_ALIVE_MAX", 100).max(1),
+        }
     }
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    let req_line = buf_reader.lines().next().unwrap().unwrap();
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
 
-    let (status_line, filename) = match &req_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "index.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(4));
-            ("HTTP/1.1 200 OK", "index.html")
+/// Writes log records to stderr, one line each, with their key-value
+/// fields appended.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
         }
-        _ => ("HTTP/1.1 404 NOT FOUND", "error.html"),
-    };
+        let mut line = format!("[{}] {}", record.level(), record.args());
+        let _ = record.key_values().visit(&mut Fields(&mut line));
+        eprintln!("{}", line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Appends each field of a record as ` key=value`.
+struct Fields<'a>(&'a mut String);
 
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push_str(&format!(" {}={}", key, value));
+        Ok(())
+    }
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Serve an HTML file from the working directory.
+fn page(status: u16, filename: &str) -> Response {
     let html = fs::read_to_string(filename).unwrap();
-    let length = html.len();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html")
+        .body(html)
+        .build()
+}
 
-    let response = format!(
-        "{status_line}\r\nContent-Length: {length}\r\nContent-Type: text/html\r\n\r\n{html}"
-    );
-    stream.write_all(response.as_bytes()).unwrap();
+/// Give the router's bare 404 and 405 answers the error page as body.
+fn with_error_page(response: Response) -> Response {
+    if !matches!(response.status(), 404 | 405) {
+        return response;
+    }
+    let mut error = page(response.status(), "error.html");
+    for (name, value) in response.headers().iter() {
+        error.headers_mut().append(name, value);
+    }
+    error
+}
+
+fn main() {
+    let config = Config::from_env();
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(config.log_level);
+    let pool = ThreadPool::builder()
+        .num_threads(4)
+        .max_threads(16)
+        .keep_alive(Duration::from_secs(30))
+        .queue_capacity(64)
+        .thread_name("http")
+        .build()
+        .unwrap();
+    let server = Server::builder()
+        .pool(pool)
+        .max_body(config.max_body)
+        .idle_timeout(config.idle_timeout)
+        .max_requests(config.max_requests)
+        .shed_load(config.max_queued, Duration::from_secs(config.retry_after))
+        .bind("127.0.0.1:7878")
+        .unwrap();
+
+    let router = Router::new()
+        .get("/", |_: Request| page(200, "index.html"))
+        .get("/sleep", |_: Request| {
+            thread::sleep(Duration::from_secs(4));
+            page(200, "index.html")
+        });
+    server.serve(move |request: Request| with_error_page(router.handle(request)));
 }
@@ -0,0 +1,149 @@
+use std::iter;
+
+use crate::ThreadPool;
+
+/// How many pieces `par_map` and `par_for_each` cut their input into per
+/// worker, so a slow piece doesn't leave the other workers idle.
+const PIECES_PER_WORKER: usize = 4;
+
+/// Batch helpers that split a collection across the workers and wait for
+/// the result, built on [`ThreadPool::scope`].
+///
+/// Results come back in input order. If any piece panics, the first panic
+/// is re-raised in the caller once the other pieces are done. Like `scope`,
+/// these must not be called from a job of the same pool.
+impl ThreadPool {
+    /// Apply `f` to every item on the workers and collect the results in
+    /// input order.
+    ///
+    /// ```
+    /// let pool = rust_server::ThreadPool::new(2);
+    /// let squares = pool.par_map(1..=4, |n| n * n);
+    /// assert_eq!(squares, [1, 4, 9, 16]);
+    /// ```
+    pub fn par_map<I, F, R>(&self, items: I, f: F) -> Vec<R>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> R + Sync,
+        R: Send,
+    {
+        let items: Vec<_> = items.into_iter().collect();
+        let pieces = self.size().max(1) * PIECES_PER_WORKER;
+        let piece_size = items.len().div_ceil(pieces).max(1);
+
+        let mut items = items.into_iter();
+        let pieces: Vec<Vec<_>> = iter::from_fn(|| {
+            let piece: Vec<_> = items.by_ref().take(piece_size).collect();
+            (!piece.is_empty()).then_some(piece)
+        })
+        .collect();
+        let mut results: Vec<Vec<R>> = pieces
+            .iter()
+            .map(|piece| Vec::with_capacity(piece.len()))
+            .collect();
+
+        let f = &f;
+        self.scope(|s| {
+            for (piece, out) in pieces.into_iter().zip(results.iter_mut()) {
+                s.execute(move || out.extend(piece.into_iter().map(f)));
+            }
+        });
+        results.into_iter().flatten().collect()
+    }
+
+    /// Call `f` with every item on the workers and wait until all calls
+    /// have returned.
+    pub fn par_for_each<I, F>(&self, items: I, f: F)
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) + Sync,
+    {
+        self.par_map(items, f);
+    }
+
+    /// Call `f` with each `chunk_size` long chunk of `items` (the last one
+    /// may be shorter) on the workers and collect the results in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    ///
+    /// ```
+    /// let pool = rust_server::ThreadPool::new(2);
+    /// let sums = pool.par_chunks(&[1, 2, 3, 4, 5], 2, |chunk| chunk.iter().sum::<i32>());
+    /// assert_eq!(sums, [3, 7, 5]);
+    /// ```
+    pub fn par_chunks<T, F, R>(&self, items: &[T], chunk_size: usize, f: F) -> Vec<R>
+    where
+        T: Sync,
+        F: Fn(&[T]) -> R + Sync,
+        R: Send,
+    {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        let mut results: Vec<Option<R>> = iter::repeat_with(|| None)
+            .take(items.len().div_ceil(chunk_size))
+            .collect();
+
+        let f = &f;
+        self.scope(|s| {
+            for (chunk, out) in items.chunks(chunk_size).zip(results.iter_mut()) {
+                s.execute(move || *out = Some(f(chunk)));
+            }
+        });
+        results
+            .into_iter()
+            .map(|result| result.expect("chunk finished without a result"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ThreadPool;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn par_map_keeps_input_order() {
+        let pool = ThreadPool::new(3);
+        let doubled = pool.par_map(0..1000, |n| n * 2);
+        assert_eq!(doubled, (0..1000).map(|n| n * 2).collect::<Vec<_>>());
+        assert!(pool.par_map(Vec::<u8>::new(), |n| n).is_empty());
+    }
+
+    #[test]
+    fn par_for_each_visits_every_item() {
+        let pool = ThreadPool::new(2);
+        let sum = AtomicUsize::new(0);
+        pool.par_for_each(1..=100, |n| {
+            sum.fetch_add(n, Ordering::SeqCst);
+        });
+        assert_eq!(sum.load(Ordering::SeqCst), 5050);
+    }
+
+    #[test]
+    fn par_chunks_keeps_chunk_order() {
+        let pool = ThreadPool::new(2);
+        let words = ["a", "b", "c", "d", "e", "f", "g"];
+        let joined = pool.par_chunks(&words, 3, |chunk| chunk.concat());
+        assert_eq!(joined, ["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn panics_reach_the_caller() {
+        let pool = ThreadPool::new(2);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.par_map(0..10, |n| {
+                if n == 7 {
+                    panic!("bad item");
+                }
+                n
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"bad item"));
+        assert_eq!(pool.par_map([1], |n| n + 1), [2]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::{stats, Job, ThreadPool};
+
+/// Lets jobs borrow from the stack of the caller of
+/// [`ThreadPool::scope`](crate::ThreadPool::scope).
+///
+/// `'scope` is the lifetime of the scope itself; jobs may borrow anything
+/// that outlives it. `'env` covers what the scope closure itself borrows.
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+/// Bookkeeping shared between a scope and its jobs.
+struct ScopeState {
+    pending: Mutex<usize>,
+    done: Condvar,
+    /// First panic of a job, re-raised once the scope is over.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl ScopeState {
+    fn record_panic(&self, payload: Box<dyn Any + Send>) {
+        self.panic.lock().unwrap().get_or_insert(payload);
+    }
+
+    fn wait(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        while *pending > 0 {
+            pending = self.done.wait(pending).unwrap();
+        }
+    }
+}
+
+/// Travels with a scoped job and counts it as finished when dropped, be
+/// it after running or because the pool threw the job away.
+struct Pending {
+    state: Arc<ScopeState>,
+    ran: bool,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if !self.ran {
+            self.state
+                .record_panic(Box::new("scoped job was dropped before it ran"));
+        }
+        *self.state.pending.lock().unwrap() -= 1;
+        self.state.done.notify_all();
+    }
+}
+
+/// A scoped job on its way through the queue.
+///
+/// Fields drop in declaration order, so `f` and everything it borrows is
+/// gone before `pending` tells the scope that the job is finished. Letting
+/// a closure capture both would leave that order unspecified.
+struct ScopedJob<F> {
+    f: Option<F>,
+    pending: Pending,
+}
+
+impl<F: FnOnce()> ScopedJob<F> {
+    fn run(mut self) {
+        self.pending.ran = true;
+        let f = self.f.take().expect("scoped job runs once");
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            stats::mark_panicked();
+            self.pending.state.record_panic(payload);
+        }
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Queue `f` to run on one of the pool's workers. `f` may borrow
+    /// anything that outlives the scope.
+    ///
+    /// If `f` panics, the panic is re-raised by `scope` once every other
+    /// job has finished, instead of going to the pool's panic handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn execute<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        *self.state.pending.lock().unwrap() += 1;
+        let scoped = ScopedJob {
+            f: Some(f),
+            pending: Pending {
+                state: Arc::clone(&self.state),
+                ran: false,
+            },
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || scoped.run());
+        // SAFETY: `scope` does not return before every job started here
+        // has run or been dropped, which is what dropping `pending` signals.
+        // `ScopedJob` drops `f` before `pending` on both paths, so until
+        // then everything the job borrows is still alive.
+        let job: Job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        self.pool.execute(job);
+    }
+}
+
+/// See [`ThreadPool::scope`](crate::ThreadPool::scope).
+pub(crate) fn scope<'env, F, T>(pool: &ThreadPool, f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        pool,
+        state: Arc::new(ScopeState {
+            pending: Mutex::new(0),
+            done: Condvar::new(),
+            panic: Mutex::new(None),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    scope.state.wait();
+    match result {
+        Err(payload) => panic::resume_unwind(payload),
+        Ok(value) => match scope.state.panic.lock().unwrap().take() {
+            Some(payload) => panic::resume_unwind(payload),
+            None => value,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::block_workers;
+    use crate::{ShutdownPolicy, ThreadPool};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn jobs_borrow_local_data() {
+        let pool = ThreadPool::new(3);
+        let mut numbers = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let total = AtomicUsize::new(0);
+        pool.scope(|s| {
+            for chunk in numbers.chunks_mut(3) {
+                let total = &total;
+                s.execute(move || {
+                    for n in chunk.iter_mut() {
+                        *n *= 10;
+                        total.fetch_add(*n, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(numbers, [10, 20, 30, 40, 50, 60, 70, 80]);
+        assert_eq!(total.load(Ordering::SeqCst), 360);
+    }
+
+    #[test]
+    fn panic_is_raised_after_all_jobs_finished() {
+        let pool = ThreadPool::new(2);
+        let finished = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| panic!("scoped"));
+                for _ in 0..4 {
+                    s.execute(|| {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        finished.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"scoped"));
+        assert_eq!(finished.load(Ordering::SeqCst), 4);
+        assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+    }
+
+    /// Borrows from the stack of the test and writes to it when dropped,
+    /// slowly, to give a premature end of the scope time to show.
+    struct SetOnDrop<'a>(&'a AtomicBool);
+
+    impl Drop for SetOnDrop<'_> {
+        fn drop(&mut self) {
+            thread::sleep(Duration::from_millis(50));
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn discarded_jobs_drop_their_borrows_before_the_scope_ends() {
+        let pool = Arc::new(
+            ThreadPool::builder()
+                .num_threads(1)
+                .shutdown_policy(ShutdownPolicy::Discard)
+                .build()
+                .unwrap(),
+        );
+        let release_tx = block_workers(&pool, 1);
+
+        let dropped = AtomicBool::new(false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                let guard = SetOnDrop(&dropped);
+                s.execute(move || drop(guard));
+                // Discard the queued job from another thread while this
+                // one waits for the scope to end.
+                let pool = Arc::clone(&pool);
+                thread::spawn(move || pool.shutdown(Duration::from_secs(5)));
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(200));
+                    release_tx.send(()).unwrap();
+                });
+            })
+        }));
+        assert!(dropped.load(Ordering::SeqCst));
+        let payload = result.unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<&str>(),
+            Some(&"scoped job was dropped before it ran")
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Instant;
+
+use log::{debug, error, warn};
+
+use crate::Shared;
+
+/// What happens to jobs still waiting in the queue when the pool shuts
+/// down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShutdownPolicy {
+    /// Workers finish every job that was queued before the shutdown.
+    #[default]
+    Drain,
+    /// Queued jobs are dropped without running; only jobs already running
+    /// are waited for.
+    Discard,
+}
+
+/// Outcome of [`ThreadPool::shutdown`](crate::ThreadPool::shutdown).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShutdownReport {
+    /// Jobs dropped from the queue under [`ShutdownPolicy::Discard`].
+    pub discarded_jobs: usize,
+    /// Ids of workers still running when the deadline passed. Their threads
+    /// are detached and left to finish on their own.
+    pub unfinished_workers: Vec<usize>,
+}
+
+impl ShutdownReport {
+    /// Whether every worker stopped before the deadline.
+    pub fn is_complete(&self) -> bool {
+        self.unfinished_workers.is_empty()
+    }
+}
+
+impl Shared {
+    /// Close the queue, apply the shutdown policy and wait for the workers
+    /// until `deadline`, or for as long as it takes if there is none.
+    pub(crate) fn shutdown(&self, deadline: Option<Instant>) -> ShutdownReport {
+        self.queue.close();
+        let discarded_jobs = match self.shutdown_policy {
+            ShutdownPolicy::Drain => 0,
+            ShutdownPolicy::Discard => self.queue.clear(),
+        };
+        self.stop_timer();
+        let all_stopped = self.wait_for_workers(deadline);
+
+        let mut unfinished_workers = Vec::new();
+        // A worker that dies while we wait puts its replacement back into
+        // the list, so keep going until nothing is left to join.
+        loop {
+            let threads: Vec<_> = self
+                .workers
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .filter_map(|worker| Some((worker.id, worker.thread.take()?)))
+                .collect();
+            if threads.is_empty() {
+                break;
+            }
+            for (id, thread) in threads {
+                if deadline.is_some() && !all_stopped && !thread.is_finished() {
+                    warn!(worker = id; "worker did not stop in time; detaching it");
+                    unfinished_workers.push(id);
+                    continue;
+                }
+                debug!(worker = id; "joining worker");
+                if thread.join().is_err() {
+                    error!(worker = id; "worker panicked while shutting down");
+                }
+            }
+            if deadline.is_some() {
+                break;
+            }
+        }
+        unfinished_workers.sort_unstable();
+        ShutdownReport {
+            discarded_jobs,
+            unfinished_workers,
+        }
+    }
+
+    /// Block until every worker thread has exited or `deadline` passes.
+    ///
+    /// Returns whether all of them exited.
+    fn wait_for_workers(&self, deadline: Option<Instant>) -> bool {
+        let mut running = self.running.lock().unwrap();
+        while *running > 0 {
+            running = match deadline {
+                None => self.stopped.wait(running).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.stopped
+                        .wait_timeout(running, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+        true
+    }
+
+    /// Called by each worker thread as it exits for good.
+    pub(crate) fn worker_stopped(&self) {
+        *self.running.lock().unwrap() -= 1;
+        self.stopped.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::block_workers;
+    use crate::ThreadPool;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn drain_runs_queued_jobs() {
+        let pool = ThreadPool::new(1);
+        let (done_tx, done_rx) = mpsc::channel();
+        for i in 0..5 {
+            let done_tx = done_tx.clone();
+            pool.execute(move || done_tx.send(i).unwrap());
+        }
+        let report = pool.shutdown(Duration::from_secs(5));
+        assert_eq!(report, ShutdownReport::default());
+        assert_eq!(done_rx.try_iter().count(), 5);
+        assert!(pool.try_execute(|| {}).is_err());
+    }
+
+    #[test]
+    fn discard_drops_queued_jobs() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .shutdown_policy(ShutdownPolicy::Discard)
+            .build()
+            .unwrap();
+        let release_tx = block_workers(&pool, 1);
+        let queued = pool.spawn(|| 1);
+        pool.execute(|| {});
+
+        // Let the running job finish only after the queue has been cleared.
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            release_tx.send(()).unwrap();
+        });
+        let report = pool.shutdown(Duration::from_secs(5));
+        assert_eq!(report.discarded_jobs, 2);
+        assert!(report.is_complete());
+        assert!(queued.join().is_err());
+    }
+
+    #[test]
+    fn stuck_worker_is_reported() {
+        let pool = ThreadPool::new(2);
+        let release_tx = block_workers(&pool, 1);
+
+        let report = pool.shutdown(Duration::from_millis(50));
+        assert_eq!(report.unfinished_workers.len(), 1);
+        assert!(!report.is_complete());
+        drop(release_tx);
+    }
+}
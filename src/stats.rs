@@ -0,0 +1,243 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Snapshot of a pool's load and history, returned by
+/// [`ThreadPool::stats`](crate::ThreadPool::stats).
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    /// Worker threads currently in the pool.
+    pub workers: usize,
+    /// Workers running a job.
+    pub active_workers: usize,
+    /// Workers waiting for a job.
+    pub idle_workers: usize,
+    /// Jobs waiting for a free worker.
+    pub queued_jobs: usize,
+    /// Id of every worker with the CPU it is pinned to, if any, ordered by
+    /// id.
+    pub worker_cpus: Vec<(usize, Option<usize>)>,
+    /// Jobs that ran to completion.
+    pub completed_jobs: u64,
+    /// Jobs that panicked, including spawned jobs whose panic was handed to
+    /// their `JobHandle`.
+    pub panicked_jobs: u64,
+    /// Jobs that were still running when their deadline passed.
+    pub overrun_jobs: u64,
+    /// Time jobs spent in the queue before a worker picked them up.
+    pub queue_wait: Histogram,
+    /// Time jobs spent running on a worker.
+    pub execution_time: Histogram,
+}
+
+/// Number of buckets. Bucket `i` counts durations below `2^i` microseconds
+/// that did not fit an earlier one; the last bucket also takes everything
+/// longer.
+const BUCKETS: usize = 31;
+
+/// Distribution of durations over power-of-two microsecond buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    total: Duration,
+}
+
+impl Histogram {
+    fn bucket(duration: Duration) -> usize {
+        let micros = duration.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+        bucket.min(BUCKETS - 1)
+    }
+
+    fn upper_bound(bucket: usize) -> Duration {
+        if bucket == BUCKETS - 1 {
+            Duration::MAX
+        } else {
+            Duration::from_micros(1 << bucket)
+        }
+    }
+
+    /// Number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of all recorded durations.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Average recorded duration, if anything was recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| self.total.div_f64(count as f64))
+    }
+
+    /// Upper bound of the bucket holding the `quantile` (between 0 and 1)
+    /// of recorded durations, e.g. `0.99` for the 99th percentile.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.counts.iter().enumerate().find_map(|(bucket, &n)| {
+            seen += n;
+            (seen >= rank).then(|| Histogram::upper_bound(bucket))
+        })
+    }
+
+    /// Non-empty buckets as `(upper bound, count)`, shortest first.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(bucket, &count)| (Histogram::upper_bound(bucket), count))
+    }
+}
+
+/// Lock-free counterpart of [`Histogram`] that workers record into.
+struct AtomicHistogram {
+    counts: [AtomicU64; BUCKETS],
+    total_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            total_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, duration: Duration) {
+        self.counts[Histogram::bucket(duration)].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Job counters and timings shared by all workers of a pool.
+pub(crate) struct Metrics {
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    overrun: AtomicU64,
+    queue_wait: AtomicHistogram,
+    execution_time: AtomicHistogram,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            overrun: AtomicU64::new(0),
+            queue_wait: AtomicHistogram::new(),
+            execution_time: AtomicHistogram::new(),
+        }
+    }
+
+    /// Record one finished job. `panicked` also covers panics that the job
+    /// caught itself and flagged with [`mark_panicked`].
+    pub(crate) fn record(&self, queue_wait: Duration, execution_time: Duration, panicked: bool) {
+        let panicked = panicked | PANICKED.with(|flag| flag.replace(false));
+        if panicked {
+            self.panicked.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.queue_wait.record(queue_wait);
+        self.execution_time.record(execution_time);
+    }
+
+    pub(crate) fn record_overrun(&self) {
+        self.overrun.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn overruns(&self) -> u64 {
+        self.overrun.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> (u64, u64, Histogram, Histogram) {
+        (
+            self.completed.load(Ordering::Relaxed),
+            self.panicked.load(Ordering::Relaxed),
+            self.queue_wait.snapshot(),
+            self.execution_time.snapshot(),
+        )
+    }
+}
+
+thread_local! {
+    static PANICKED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Count the job running on this worker as panicked even though it caught
+/// the panic itself, as `spawn` does to hand it to the `JobHandle`.
+pub(crate) fn mark_panicked() {
+    PANICKED.with(|flag| flag.set(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::time::Instant;
+
+    #[test]
+    fn histogram_buckets_and_quantiles() {
+        let metrics = Metrics::new();
+        for micros in [0, 3, 3, 100, 5_000] {
+            metrics.record(Duration::ZERO, Duration::from_micros(micros), false);
+        }
+        let (completed, panicked, _, execution_time) = metrics.snapshot();
+        assert_eq!((completed, panicked), (5, 0));
+        assert_eq!(execution_time.count(), 5);
+        assert_eq!(execution_time.total(), Duration::from_micros(5_106));
+        assert_eq!(execution_time.quantile(0.5), Some(Duration::from_micros(4)));
+        assert_eq!(
+            execution_time.quantile(1.0),
+            Some(Duration::from_micros(8192))
+        );
+        assert_eq!(
+            execution_time.buckets().collect::<Vec<_>>(),
+            vec![
+                (Duration::from_micros(1), 1),
+                (Duration::from_micros(4), 2),
+                (Duration::from_micros(128), 1),
+                (Duration::from_micros(8192), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_count_completed_and_panicked_jobs() {
+        let pool = ThreadPool::new(2);
+        pool.set_panic_handler(|_, _| {});
+        pool.spawn(|| ()).join().unwrap();
+        assert!(pool.spawn(|| panic!("spawned")).join().is_err());
+        pool.execute(|| panic!("executed"));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut stats = pool.stats();
+        while stats.completed_jobs + stats.panicked_jobs < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+            stats = pool.stats();
+        }
+        assert_eq!(stats.completed_jobs, 1);
+        assert_eq!(stats.panicked_jobs, 2);
+        assert_eq!(stats.workers, 2);
+        assert_eq!(stats.queued_jobs, 0);
+        assert_eq!(stats.execution_time.count(), 3);
+        assert_eq!(stats.queue_wait.count(), 3);
+    }
+}
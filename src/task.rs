@@ -0,0 +1,299 @@
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::{handle, stats, Job, JobHandle, Priority, Shared, ThreadPool};
+
+/// Not queued; waiting for its waker.
+const IDLE: u8 = 0;
+/// In the queue, waiting for a worker to poll it.
+const SCHEDULED: u8 = 1;
+/// Being polled by a worker.
+const RUNNING: u8 = 2;
+/// Woken while being polled; goes straight back into the queue.
+const NOTIFIED: u8 = 3;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// A future spawned on the pool. Every poll is an ordinary job; waking the
+/// task queues the next one.
+struct Task {
+    /// `None` once the future has completed.
+    future: Mutex<Option<BoxFuture>>,
+    state: AtomicU8,
+    shared: Weak<Shared>,
+}
+
+impl Task {
+    /// Queue a job that polls the task after it was woken. Returns `false`
+    /// if the pool is gone or shut down, in which case the task is dropped
+    /// with its last waker.
+    ///
+    /// Wakers are often called on a worker, e.g. by a finishing job the
+    /// task awaits, so the job goes in even if a bounded queue is full.
+    fn schedule(self: &Arc<Self>) -> bool {
+        let Some(shared) = self.shared.upgrade() else {
+            return false;
+        };
+        if shared
+            .queue
+            .push_past_capacity(self.poll_job(), Priority::Normal)
+            .is_err()
+        {
+            return false;
+        }
+        shared.grow_if_backlogged();
+        true
+    }
+
+    fn poll_job(self: &Arc<Self>) -> Job {
+        let task = Arc::clone(self);
+        Box::new(move || task.run())
+    }
+
+    fn run(self: Arc<Self>) {
+        self.state.store(RUNNING, Ordering::SeqCst);
+        let mut future = self.future.lock().unwrap();
+        let Some(polled) = future.as_mut() else {
+            return;
+        };
+        let waker = Waker::from(Arc::clone(&self));
+        if polled
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready()
+        {
+            *future = None;
+            return;
+        }
+        drop(future);
+        let parked = self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst);
+        if parked.is_err() {
+            // Woken while it was being polled.
+            self.state.store(SCHEDULED, Ordering::SeqCst);
+            self.schedule();
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::SeqCst);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => break,
+                Err(actual) => state = actual,
+            }
+        }
+        if state == IDLE {
+            self.schedule();
+        }
+    }
+}
+
+/// Turns a panic while polling the inner future into its output, so it can
+/// be handed to the `JobHandle` like the panic of a plain job.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = thread::Result<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &mut self.get_mut().0;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(value)) => Poll::Ready(Ok(value)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Run `future` on the workers and return a handle to its output.
+    ///
+    /// Each poll of the future runs as a job. When the future is woken,
+    /// the next poll is queued behind the jobs already waiting, so async
+    /// tasks share the workers fairly with plain jobs. Only the first poll
+    /// waits for room in a bounded queue; later ones are queued even if it
+    /// is full, since the waker may run on a worker. A panic while
+    /// polling is handed to whoever joins or awaits the handle.
+    ///
+    /// Futures must not block a worker for long between `await` points;
+    /// in particular, calling [`block_on`] from one can deadlock the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has been shut down.
+    pub fn spawn_future<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (completer, handle) = handle::pair();
+        let future = CatchUnwind(Box::pin(future));
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(async move {
+                let result = future.await;
+                if result.is_err() {
+                    stats::mark_panicked();
+                }
+                completer.complete(result);
+            }))),
+            state: AtomicU8::new(SCHEDULED),
+            shared: Arc::downgrade(&self.shared),
+        });
+        // The first poll waits for room like any other submitted job.
+        if self
+            .shared
+            .queue
+            .push(task.poll_job(), Priority::Normal)
+            .is_err()
+        {
+            panic!("ThreadPool::spawn_future called after shutdown");
+        }
+        self.shared.grow_if_backlogged();
+        handle
+    }
+}
+
+/// Wakes the thread blocked in [`block_on`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Run `future` to completion on the current thread, sleeping whenever it
+/// has to wait.
+///
+/// ```
+/// let pool = rust_server::ThreadPool::new(2);
+/// let answer = pool.spawn_future(async { 6 * 7 });
+/// assert_eq!(rust_server::block_on(answer).unwrap(), 42);
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::block_workers;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Returns `Pending` `n` times, waking itself each time, like a future
+    /// waiting on a quick I/O event.
+    struct YieldTimes(u32);
+
+    impl Future for YieldTimes {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 == 0 {
+                return Poll::Ready(());
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn futures_run_on_the_workers() {
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .thread_name("async")
+            .build()
+            .unwrap();
+        let handle = pool.spawn_future(async {
+            YieldTimes(5).await;
+            thread::current().name().unwrap().starts_with("async-")
+        });
+        assert!(block_on(handle).unwrap());
+        // One job per poll: five times pending, then ready.
+        pool.wait_idle();
+        assert_eq!(pool.stats().completed_jobs, 6);
+    }
+
+    #[test]
+    fn futures_await_other_jobs() {
+        let pool = ThreadPool::new(2);
+        let slow = pool.spawn(|| {
+            thread::sleep(Duration::from_millis(20));
+            20
+        });
+        let handle = pool.spawn_future(async move { slow.await.unwrap() + 1 });
+        assert_eq!(handle.join().unwrap(), 21);
+    }
+
+    #[test]
+    fn wakers_never_wait_for_room_in_a_bounded_queue() {
+        let pool = ThreadPool::bounded(1, 2);
+        let release_tx = block_workers(&pool, 1);
+        // Runs after the task has polled it once, so finishing it wakes
+        // the task from the worker.
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let awaited = pool.spawn_with_priority(Priority::Low, move || {
+            let _ = finish_rx.recv();
+        });
+        let task = pool.spawn_future(async move { awaited.await.is_ok() });
+        drop(release_tx);
+        while pool.queued_jobs() > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let (ran_tx, ran_rx) = mpsc::channel();
+        for _ in 0..2 {
+            let ran_tx = ran_tx.clone();
+            pool.execute(move || ran_tx.send(()).unwrap());
+        }
+        finish_tx.send(()).unwrap();
+        assert!(task
+            .join_timeout(Duration::from_secs(5))
+            .ok()
+            .unwrap()
+            .unwrap());
+        assert_eq!(ran_rx.iter().take(2).count(), 2);
+    }
+
+    #[test]
+    fn panicking_future_reaches_the_handle() {
+        let pool = ThreadPool::new(1);
+        let handle = pool.spawn_future(async {
+            YieldTimes(1).await;
+            panic!("async boom");
+        });
+        let payload = block_on(handle).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"async boom"));
+        assert_eq!(block_on(pool.spawn_future(async { 3 })).unwrap(), 3);
+    }
+}
@@ -0,0 +1,410 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::{Job, Priority, Shared};
+
+/// Handle to a job started with
+/// [`ThreadPool::execute_after`](crate::ThreadPool::execute_after) or
+/// [`ThreadPool::execute_every`](crate::ThreadPool::execute_every).
+///
+/// Dropping the handle does not cancel the job.
+#[derive(Debug, Clone)]
+pub struct ScheduledHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduledHandle {
+    /// Stop the job from running (again). A run that has already started
+    /// is not interrupted.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether [`cancel`](ScheduledHandle::cancel) has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Periodic job; it is moved from run to run, never shared.
+type Repeating = Box<dyn FnMut() + Send + 'static>;
+
+enum Task {
+    Once(Job),
+    Every(Repeating, Duration),
+    /// Internal check run on the timer thread itself, so it must be quick.
+    Watch(Job),
+    /// Job of a due entry that found the queue full, waiting to try again.
+    Retry(Job),
+}
+
+/// How long a due job that found the queue full waits before it tries
+/// again.
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// A job waiting for its time to come.
+struct Entry {
+    at: Instant,
+    /// Keeps entries due at the same instant in submission order.
+    seq: u64,
+    task: Task,
+    cancelled: Arc<AtomicBool>,
+}
+
+// `BinaryHeap` is a max-heap; order entries so the earliest is on top.
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> CmpOrdering {
+        other.at.cmp(&self.at).then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// Delayed and periodic jobs of a pool.
+///
+/// A single timer thread, started on first use, sleeps until the earliest
+/// entry is due and then hands it to the workers through the normal queue.
+pub(crate) struct Timer {
+    state: Mutex<TimerState>,
+    changed: Condvar,
+}
+
+struct TimerState {
+    entries: BinaryHeap<Entry>,
+    /// Watches dropped through `unwatch` since the heap was last purged.
+    unwatched: usize,
+    next_seq: u64,
+    thread: Option<thread::JoinHandle<()>>,
+    stopped: bool,
+}
+
+impl Timer {
+    pub(crate) fn new() -> Timer {
+        Timer {
+            state: Mutex::new(TimerState {
+                entries: BinaryHeap::new(),
+                unwatched: 0,
+                next_seq: 0,
+                thread: None,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+impl Shared {
+    /// Run `job` once, after `delay`.
+    ///
+    /// Returns `None` if the pool has been shut down.
+    pub(crate) fn schedule_once(
+        self: &Arc<Self>,
+        delay: Duration,
+        job: Job,
+    ) -> Option<ScheduledHandle> {
+        self.schedule_task(Instant::now() + delay, Task::Once(job))
+    }
+
+    /// Run `f` every `interval`, starting one interval from now.
+    ///
+    /// Returns `None` if the pool has been shut down.
+    pub(crate) fn schedule_repeating(
+        self: &Arc<Self>,
+        interval: Duration,
+        f: Repeating,
+    ) -> Option<ScheduledHandle> {
+        self.schedule_task(Instant::now() + interval, Task::Every(f, interval))
+    }
+
+    /// Call `check` on the timer thread at `at`, even if every worker is
+    /// busy.
+    ///
+    /// Returns the flag to pass to [`unwatch`](Shared::unwatch) once the
+    /// check is no longer needed.
+    pub(crate) fn watch(self: &Arc<Self>, at: Instant, check: Job) -> Arc<AtomicBool> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.schedule(at, Task::Watch(check), Arc::clone(&cancelled));
+        cancelled
+    }
+
+    /// Drop the check of a [`watch`](Shared::watch) before it is due.
+    ///
+    /// The entry is skipped once it reaches the top of the heap. So that
+    /// watches with far-off deadlines don't pile up meanwhile, the heap is
+    /// purged whenever dropped watches could make up half of it.
+    pub(crate) fn unwatch(&self, cancelled: &AtomicBool) {
+        if cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let mut state = self.timer.state.lock().unwrap();
+        state.unwatched += 1;
+        if state.unwatched * 2 > state.entries.len() {
+            state
+                .entries
+                .retain(|entry| !entry.cancelled.load(Ordering::SeqCst));
+            state.unwatched = 0;
+        }
+    }
+
+    fn schedule_task(self: &Arc<Self>, at: Instant, task: Task) -> Option<ScheduledHandle> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.schedule(at, task, Arc::clone(&cancelled))
+            .then_some(ScheduledHandle { cancelled })
+    }
+
+    fn schedule(self: &Arc<Self>, at: Instant, task: Task, cancelled: Arc<AtomicBool>) -> bool {
+        let mut state = self.timer.state.lock().unwrap();
+        if state.stopped {
+            return false;
+        }
+        if state.thread.is_none() {
+            let shared = Arc::clone(self);
+            let thread = thread::Builder::new()
+                .name(format!("{}-timer", self.thread_config.name_prefix))
+                .spawn(move || shared.run_timer())
+                .unwrap_or_else(|err| panic!("failed to spawn the timer thread: {err}"));
+            state.thread = Some(thread);
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.entries.push(Entry {
+            at,
+            seq,
+            task,
+            cancelled,
+        });
+        self.timer.changed.notify_one();
+        true
+    }
+
+    /// Body of the timer thread.
+    fn run_timer(self: Arc<Self>) {
+        let mut state = self.timer.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                break;
+            }
+            let now = Instant::now();
+            let wait = match state.entries.peek() {
+                None => None,
+                Some(entry) if entry.cancelled.load(Ordering::SeqCst) => {
+                    state.entries.pop();
+                    continue;
+                }
+                Some(entry) if entry.at > now => Some(entry.at - now),
+                Some(_) => {
+                    let entry = state.entries.pop().unwrap();
+                    drop(state);
+                    self.fire(entry);
+                    state = self.timer.state.lock().unwrap();
+                    continue;
+                }
+            };
+            state = match wait {
+                Some(wait) => self.timer.changed.wait_timeout(state, wait).unwrap().0,
+                None => self.timer.changed.wait(state).unwrap(),
+            };
+        }
+        debug!("timer stopped");
+    }
+
+    /// Queue a due entry for the workers.
+    fn fire(self: &Arc<Self>, entry: Entry) {
+        let Entry {
+            at,
+            task,
+            cancelled,
+            ..
+        } = entry;
+        if cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        let retry = Arc::clone(&cancelled);
+        let job: Job = match task {
+            Task::Watch(check) => return check(),
+            Task::Retry(job) => job,
+            Task::Once(job) => Box::new(move || {
+                if !cancelled.load(Ordering::SeqCst) {
+                    job();
+                }
+            }),
+            Task::Every(mut f, interval) => {
+                let shared = Arc::downgrade(self);
+                Box::new(move || {
+                    if cancelled.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let result = panic::catch_unwind(AssertUnwindSafe(&mut f));
+                    // The next run is only scheduled once this one is over,
+                    // so runs never overlap; ticks missed meanwhile are
+                    // skipped rather than run back to back.
+                    if let Some(shared) = shared.upgrade() {
+                        if !cancelled.load(Ordering::SeqCst) {
+                            let next = (at + interval).max(Instant::now());
+                            shared.schedule(next, Task::Every(f, interval), cancelled);
+                        }
+                    }
+                    if let Err(payload) = result {
+                        panic::resume_unwind(payload);
+                    }
+                })
+            }
+        };
+        // Waiting for room in a bounded queue would hold up every other
+        // entry, deadline watches included, so try again a little later.
+        match self.queue.try_push(job, Priority::Normal) {
+            Ok(()) => self.grow_if_backlogged(),
+            Err(job) if !self.queue.is_closed() => {
+                self.schedule(Instant::now() + RETRY_DELAY, Task::Retry(job), retry);
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Drop every pending entry and stop the timer thread, if it started.
+    pub(crate) fn stop_timer(&self) {
+        let thread = {
+            let mut state = self.timer.state.lock().unwrap();
+            state.stopped = true;
+            state.entries.clear();
+            self.timer.changed.notify_all();
+            state.thread.take()
+        };
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::block_workers;
+    use crate::ThreadPool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn delayed_jobs_run_in_deadline_order() {
+        let pool = ThreadPool::new(1);
+        let (done_tx, done_rx) = mpsc::channel();
+        let start = Instant::now();
+        for (delay, name) in [(60, "third"), (20, "first"), (40, "second")] {
+            let done_tx = done_tx.clone();
+            pool.execute_after(Duration::from_millis(delay), move || {
+                done_tx.send(name).unwrap();
+            });
+        }
+        let order: Vec<_> = done_rx.iter().take(3).collect();
+        assert_eq!(order, ["first", "second", "third"]);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn cancelled_jobs_do_not_run() {
+        let pool = ThreadPool::new(1);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        let once = pool.execute_after(Duration::from_millis(20), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        once.cancel();
+        assert!(once.is_cancelled());
+
+        let (tick_tx, tick_rx) = mpsc::channel();
+        let every = pool.execute_every(Duration::from_millis(5), move || {
+            let _ = tick_tx.send(());
+        });
+        for _ in 0..3 {
+            tick_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        every.cancel();
+        std::thread::sleep(Duration::from_millis(50));
+        while tick_rx.try_recv().is_ok() {}
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(tick_rx.try_recv().is_err());
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn periodic_job_survives_panics() {
+        let pool = ThreadPool::new(1);
+        pool.set_panic_handler(|_, _| {});
+        let (tick_tx, tick_rx) = mpsc::channel();
+        let mut runs = 0;
+        let every = pool.execute_every(Duration::from_millis(5), move || {
+            runs += 1;
+            let _ = tick_tx.send(runs);
+            if runs == 1 {
+                panic!("first run fails");
+            }
+        });
+        assert_eq!(tick_rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+        assert_eq!(tick_rx.recv_timeout(Duration::from_secs(5)), Ok(2));
+        every.cancel();
+    }
+
+    #[test]
+    fn full_queue_does_not_hold_up_the_timer() {
+        let pool = ThreadPool::bounded(1, 1);
+        // Pin the only worker and fill the only queue slot.
+        let release_tx = block_workers(&pool, 1);
+        pool.execute(|| {});
+
+        let (ran_tx, ran_rx) = mpsc::channel();
+        pool.execute_after(Duration::from_millis(5), move || ran_tx.send(()).unwrap());
+        let (checked_tx, checked_rx) = mpsc::channel();
+        let at = Instant::now() + Duration::from_millis(20);
+        pool.shared
+            .watch(at, Box::new(move || checked_tx.send(()).unwrap()));
+        checked_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(ran_rx.try_recv().is_err());
+
+        drop(release_tx);
+        ran_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn finished_jobs_leave_no_deadline_watches_behind() {
+        let pool = ThreadPool::new(1);
+        for _ in 0..100 {
+            pool.execute_with_timeout(Duration::from_secs(3600), |_| {});
+        }
+        // The single worker only gets here once every job above is done.
+        pool.spawn(|| ()).join().unwrap();
+        let entries = pool.shared.timer.state.lock().unwrap().entries.len();
+        assert!(entries <= 1, "{entries} watches left");
+    }
+
+    #[test]
+    fn shutdown_drops_pending_timers() {
+        let pool = ThreadPool::new(1);
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        pool.execute_after(Duration::from_millis(50), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(pool.shutdown(Duration::from_secs(5)).is_complete());
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,305 @@
+use std::cell::Cell;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, trace, warn};
+
+use crate::context::{self, ContextFactory};
+use crate::queue::{JobQueue, Pop};
+use crate::{affinity, panic_message, BuildError, Shared};
+
+/// Called with the worker id on the worker's own thread.
+pub(crate) type ThreadHook = dyn Fn(usize) + Send + Sync + 'static;
+
+/// How worker threads are spawned, as configured on the builder.
+pub(crate) struct ThreadConfig {
+    pub(crate) name_prefix: String,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) on_start: Option<Arc<ThreadHook>>,
+    pub(crate) on_stop: Option<Arc<ThreadHook>>,
+    /// How long a worker above the core count may sit idle before it exits.
+    pub(crate) keep_alive: Duration,
+    /// CPUs to pin workers to, handed out by worker id.
+    pub(crate) cpus: Option<Vec<usize>>,
+    pub(crate) context: Option<Arc<ContextFactory>>,
+}
+
+impl ThreadConfig {
+    fn cpu_for(&self, id: usize) -> Option<usize> {
+        self.cpus.as_ref().map(|cpus| cpus[id % cpus.len()])
+    }
+}
+
+thread_local! {
+    static CURRENT_ID: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Id of the worker running on this thread, if it is a worker thread.
+pub(crate) fn current_id() -> Option<usize> {
+    CURRENT_ID.with(Cell::get)
+}
+
+pub struct Worker {
+    pub(crate) id: usize,
+    pub(crate) thread: Option<thread::JoinHandle<()>>,
+    /// Set once the worker has decided to exit because the pool shrank.
+    pub(crate) retired: bool,
+    /// CPU the thread pinned itself to.
+    pub(crate) cpu: Option<usize>,
+}
+
+impl Worker {
+    pub(crate) fn new(id: usize, shared: Arc<Shared>) -> io::Result<Worker> {
+        *shared.running.lock().unwrap() += 1;
+        let thread =
+            Worker::spawn(id, Arc::clone(&shared)).inspect_err(|_| shared.worker_stopped())?;
+        Ok(Worker {
+            id,
+            thread: Some(thread),
+            retired: false,
+            cpu: None,
+        })
+    }
+
+    fn spawn(id: usize, shared: Arc<Shared>) -> io::Result<thread::JoinHandle<()>> {
+        let config = &shared.thread_config;
+        let mut builder = thread::Builder::new().name(format!("{}-{}", config.name_prefix, id));
+        if let Some(size) = config.stack_size {
+            builder = builder.stack_size(size);
+        }
+        builder.spawn(move || {
+            let mut sentinel = Sentinel {
+                id,
+                shared: Arc::clone(&shared),
+                respawn: false,
+            };
+            let shared = &shared;
+            CURRENT_ID.with(|current| current.set(Some(id)));
+            if let Some(cpu) = shared.thread_config.cpu_for(id) {
+                pin(id, cpu, shared);
+            }
+            if let Some(factory) = &shared.thread_config.context {
+                context::init(id, factory.as_ref());
+            }
+            if let Some(on_start) = &shared.thread_config.on_start {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| on_start(id))) {
+                    shared.start_failed(id, panic_message(payload.as_ref()));
+                    return;
+                }
+            }
+            shared.worker_started();
+            // From here on a panic is a job's doing, and a replacement
+            // thread would get as far as this one did.
+            sentinel.respawn = true;
+            let mut idle_since = Instant::now();
+            loop {
+                // Read the epoch before deciding, so a resize that lands in
+                // between cuts the wait short.
+                let epoch = shared.queue.epoch();
+                if shared.try_retire(id, idle_since) {
+                    debug!(worker = id; "worker is no longer needed; shutting down");
+                    break;
+                }
+                match shared.queue.pop(shared.idle_timeout(idle_since), epoch) {
+                    Pop::Job(task) => {
+                        shared.active.fetch_add(1, Ordering::SeqCst);
+                        let _finished = Finished(&shared.queue);
+                        let started = Instant::now();
+                        let queue_wait = started - task.enqueued_at;
+                        trace!(
+                            worker = id, queue_wait:? = queue_wait;
+                            "worker got a job; executing"
+                        );
+                        let result = panic::catch_unwind(AssertUnwindSafe(task.job));
+                        let duration = started.elapsed();
+                        debug!(worker = id, duration:? = duration; "job finished");
+                        shared.metrics.record(queue_wait, duration, result.is_err());
+                        shared.active.fetch_sub(1, Ordering::SeqCst);
+                        if let Err(payload) = result {
+                            shared.report_panic(id, payload.as_ref());
+                        }
+                        idle_since = Instant::now();
+                    }
+                    Pop::Empty => {}
+                    Pop::Closed => {
+                        debug!(worker = id; "queue closed; shutting down");
+                        break;
+                    }
+                };
+            }
+            if let Some(on_stop) = &shared.thread_config.on_stop {
+                on_stop(id);
+            }
+        })
+    }
+}
+
+/// Pin the calling worker thread to `cpu` and note it for the stats.
+fn pin(id: usize, cpu: usize, shared: &Shared) {
+    if let Err(err) = affinity::pin_current_thread(cpu) {
+        warn!(worker = id, cpu = cpu, error:% = err; "failed to pin worker to its CPU");
+        return;
+    }
+    debug!(worker = id, cpu = cpu; "worker pinned to CPU");
+    // Whoever spawned this thread holds the lock until the worker is in
+    // the list.
+    let mut workers = shared
+        .workers
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(worker) = workers.iter_mut().find(|worker| worker.id == id) {
+        worker.cpu = Some(cpu);
+    }
+}
+
+/// How far the workers got with their start hooks.
+#[derive(Default)]
+pub(crate) struct Startup {
+    started: usize,
+    hook_panic: Option<String>,
+}
+
+impl Shared {
+    fn worker_started(&self) {
+        self.startup.lock().unwrap().started += 1;
+        self.started.notify_all();
+    }
+
+    /// The start hook of worker `id` panicked. The worker gives up its
+    /// slot rather than being replaced by a thread that would panic too.
+    fn start_failed(&self, id: usize, message: &str) {
+        error!(worker = id; "thread start hook panicked: {message}");
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(worker) = workers.iter_mut().find(|worker| worker.id == id) {
+            worker.retired = true;
+            self.live.fetch_sub(1, Ordering::SeqCst);
+        }
+        drop(workers);
+        self.startup
+            .lock()
+            .unwrap()
+            .hook_panic
+            .get_or_insert_with(|| message.to_string());
+        self.started.notify_all();
+    }
+
+    /// Block until `count` workers got through their start hooks, or fail
+    /// as soon as one hook panics.
+    pub(crate) fn wait_started(&self, count: usize) -> Result<(), BuildError> {
+        let mut startup = self.startup.lock().unwrap();
+        loop {
+            if let Some(message) = &startup.hook_panic {
+                return Err(BuildError::StartHook(message.clone()));
+            }
+            if startup.started >= count {
+                return Ok(());
+            }
+            startup = self.started.wait(startup).unwrap();
+        }
+    }
+}
+
+/// Tells the queue a job is over once everything about it, reporting its
+/// panic included, is done, even if the worker dies on the way.
+struct Finished<'a>(&'a JobQueue);
+
+impl Drop for Finished<'_> {
+    fn drop(&mut self) {
+        self.0.job_done();
+    }
+}
+
+/// Lives on a worker thread and replaces the thread if it unwinds after
+/// reaching its job loop, so the pool never loses capacity.
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+    /// Set once the worker is through its start-up.
+    respawn: bool,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if !self.respawn || !thread::panicking() {
+            self.shared.worker_stopped();
+            return;
+        }
+        warn!(worker = self.id; "worker died; spawning a replacement");
+        let thread = match Worker::spawn(self.id, Arc::clone(&self.shared)) {
+            Ok(thread) => thread,
+            Err(err) => {
+                error!(worker = self.id, error:% = err; "failed to respawn worker");
+                self.shared.worker_stopped();
+                return;
+            }
+        };
+        let mut workers = self
+            .shared
+            .workers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(worker) = workers.iter_mut().find(|worker| worker.id == self.id) {
+            worker.thread = Some(thread);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ThreadPool;
+    use log::kv::Key;
+    use log::{Level, Log, Metadata, Record};
+    use std::sync::Mutex;
+    use std::thread;
+
+    /// Thread name prefix of the pool under test, so records logged by
+    /// other tests running at the same time are left out.
+    const PREFIX: &str = "log-capture";
+
+    /// Keeps the worker id of every "job finished" record logged by the pool
+    /// under test, and whether it came with a duration.
+    struct Capture(Mutex<Vec<(Option<u64>, bool)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let ours = thread::current()
+                .name()
+                .is_some_and(|name| name.starts_with(PREFIX));
+            if ours && record.level() == Level::Debug && record.args().to_string() == "job finished"
+            {
+                let kvs = record.key_values();
+                let worker = kvs.get(Key::from("worker")).and_then(|v| v.to_u64());
+                let timed = kvs.get(Key::from("duration")).is_some();
+                self.0.lock().unwrap().push((worker, timed));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    #[test]
+    fn finished_jobs_are_logged_with_worker_id() {
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .thread_name(PREFIX)
+            .build()
+            .unwrap();
+        pool.spawn(|| ()).join().unwrap();
+        drop(pool);
+
+        assert_eq!(*CAPTURE.0.lock().unwrap(), [(Some(0), true)]);
+    }
+}